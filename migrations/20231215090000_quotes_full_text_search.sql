@@ -0,0 +1,8 @@
+-- Full-text search over the quote text and the book it comes from
+ALTER TABLE quotes
+  ADD COLUMN search tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', quote), 'A') ||
+    setweight(to_tsvector('english', book), 'B')
+  ) STORED;
+
+CREATE INDEX quotes_search_idx ON quotes USING GIN (search);
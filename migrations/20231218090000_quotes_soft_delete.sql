@@ -0,0 +1,8 @@
+-- Deleted quotes are kept in a trash until they are purged
+ALTER TABLE quotes ADD COLUMN deleted_at TIMESTAMPTZ;
+
+CREATE INDEX quotes_deleted_at_idx ON quotes (deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- A quote in the trash should not block adding it again
+ALTER TABLE quotes DROP CONSTRAINT quotes_book_quote_key;
+CREATE UNIQUE INDEX quotes_book_quote_key ON quotes (book, quote) WHERE deleted_at IS NULL;
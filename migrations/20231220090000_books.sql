@@ -0,0 +1,52 @@
+-- Books become their own entity, referenced by the quotes taken from them
+CREATE TABLE IF NOT EXISTS books (
+  id UUID PRIMARY KEY,
+  title varchar NOT NULL,
+  author varchar,
+  isbn varchar,
+  published_year INTEGER,
+  inserted_at TIMESTAMPTZ NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE UNIQUE INDEX books_title_key ON books (lower(title));
+CREATE UNIQUE INDEX books_isbn_key ON books (isbn);
+
+-- One book per title, ignoring case and surrounding whitespace
+INSERT INTO books (id, title, inserted_at, updated_at)
+SELECT gen_random_uuid(), title, inserted_at, inserted_at
+FROM (
+  SELECT DISTINCT ON (lower(btrim(book))) btrim(book) AS title, inserted_at
+  FROM quotes
+  ORDER BY lower(btrim(book)), inserted_at
+) AS titles;
+
+ALTER TABLE quotes ADD COLUMN book_id UUID REFERENCES books (id);
+
+UPDATE quotes
+SET book_id = books.id
+FROM books
+WHERE lower(btrim(quotes.book)) = lower(books.title);
+
+-- Spell every title the way its book does. Live quotes that only differ in
+-- the spelling of their title are left alone but for one, so as not to
+-- duplicate a quote; the one already spelled that way wins, or else the oldest.
+UPDATE quotes
+SET book = books.title
+FROM books
+WHERE quotes.book_id = books.id
+  AND quotes.book <> books.title
+  AND (
+    quotes.deleted_at IS NOT NULL
+    OR quotes.id IN (
+      SELECT DISTINCT ON (live.book_id, live.quote) live.id
+      FROM quotes AS live
+      JOIN books AS book ON book.id = live.book_id
+      WHERE live.deleted_at IS NULL
+      ORDER BY live.book_id, live.quote, live.book = book.title DESC, live.inserted_at, live.id
+    )
+  );
+
+ALTER TABLE quotes ALTER COLUMN book_id SET NOT NULL;
+
+CREATE INDEX quotes_book_id_idx ON quotes (book_id);
@@ -0,0 +1,32 @@
+-- Authors are credited through their books, or directly on standalone quotes
+CREATE TABLE IF NOT EXISTS authors (
+  id UUID PRIMARY KEY,
+  name varchar NOT NULL,
+  inserted_at TIMESTAMPTZ NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX authors_name_idx ON authors (lower(name));
+
+INSERT INTO authors (id, name, inserted_at, updated_at)
+SELECT gen_random_uuid(), name, inserted_at, inserted_at
+FROM (
+  SELECT DISTINCT ON (lower(btrim(author))) btrim(author) AS name, inserted_at
+  FROM books
+  WHERE btrim(author) <> ''
+  ORDER BY lower(btrim(author)), inserted_at
+) AS names;
+
+ALTER TABLE books ADD COLUMN author_id UUID REFERENCES authors (id);
+
+UPDATE books
+SET author_id = authors.id
+FROM authors
+WHERE lower(btrim(books.author)) = lower(authors.name);
+
+ALTER TABLE books DROP COLUMN author;
+
+ALTER TABLE quotes ADD COLUMN author_id UUID REFERENCES authors (id);
+
+CREATE INDEX books_author_id_idx ON books (author_id);
+CREATE INDEX quotes_author_id_idx ON quotes (author_id);
@@ -0,0 +1,14 @@
+-- Tag names are stored lowercased, which makes them case-insensitive
+CREATE TABLE IF NOT EXISTS tags (
+  id UUID PRIMARY KEY,
+  name varchar NOT NULL UNIQUE,
+  inserted_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS quote_tags (
+  quote_id UUID NOT NULL REFERENCES quotes (id) ON DELETE CASCADE,
+  tag_id UUID NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+  PRIMARY KEY (quote_id, tag_id)
+);
+
+CREATE INDEX quote_tags_tag_id_idx ON quote_tags (tag_id);
@@ -0,0 +1,46 @@
+-- Every change to the book, author, text or trash state of a quote keeps the
+-- version it replaced, along with the caller that made the change, which
+-- every write stores in `updated_by`.
+ALTER TABLE quotes ADD COLUMN updated_by varchar;
+
+CREATE TABLE IF NOT EXISTS quote_revisions (
+  quote_id UUID NOT NULL REFERENCES quotes (id) ON DELETE CASCADE,
+  revision INTEGER NOT NULL,
+  operation varchar NOT NULL,
+  book varchar NOT NULL,
+  author_id UUID,
+  quote TEXT NOT NULL,
+  actor varchar,
+  recorded_at TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY (quote_id, revision)
+);
+
+-- The trigger runs once the row is locked, so revisions of a quote are
+-- numbered without gaps or races.
+CREATE FUNCTION record_quote_revision() RETURNS trigger AS $$
+BEGIN
+  INSERT INTO quote_revisions (quote_id, revision, operation, book, author_id, quote, actor, recorded_at)
+  SELECT OLD.id,
+    COALESCE(max(revision), 0) + 1,
+    CASE
+      WHEN OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN 'delete'
+      WHEN OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN 'restore'
+      ELSE 'update'
+    END,
+    OLD.book,
+    OLD.author_id,
+    OLD.quote,
+    NEW.updated_by,
+    now()
+  FROM quote_revisions
+  WHERE quote_id = OLD.id;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER quotes_record_revision
+  BEFORE UPDATE ON quotes
+  FOR EACH ROW
+  WHEN ((OLD.book, OLD.author_id, OLD.quote, OLD.deleted_at)
+    IS DISTINCT FROM (NEW.book, NEW.author_id, NEW.quote, NEW.deleted_at))
+  EXECUTE FUNCTION record_quote_revision();
@@ -0,0 +1,8 @@
+-- Only a hash of each key is kept; the key itself is shown once, when issued
+CREATE TABLE IF NOT EXISTS api_keys (
+  id UUID PRIMARY KEY,
+  name varchar NOT NULL,
+  key_hash bytea NOT NULL UNIQUE,
+  inserted_at TIMESTAMPTZ NOT NULL,
+  revoked_at TIMESTAMPTZ
+);
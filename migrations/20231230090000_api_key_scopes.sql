@@ -0,0 +1,2 @@
+-- Keys issued before scopes existed keep the access they had
+ALTER TABLE api_keys ADD COLUMN scopes varchar NOT NULL DEFAULT 'quotes:read quotes:write';
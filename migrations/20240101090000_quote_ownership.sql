@@ -0,0 +1,5 @@
+-- Quotes remember who added them. Quotes added before callers were
+-- identified have no owner, so only admins may change them.
+ALTER TABLE quotes ADD COLUMN created_by varchar;
+
+CREATE INDEX IF NOT EXISTS quotes_created_by_idx ON quotes (created_by);
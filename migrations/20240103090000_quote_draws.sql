@@ -0,0 +1,111 @@
+-- Live quotes are numbered densely from 0 in `draw_position`, so that a
+-- random one is found by its number rather than by counting up to it. The
+-- numbers quotes free when they leave go to the ones numbered last.
+-- `live_quotes` keeps the count; its row lock orders the writes that add or
+-- remove live quotes.
+ALTER TABLE quotes ADD COLUMN draw_position bigint;
+
+CREATE TABLE live_quotes (
+  id boolean PRIMARY KEY DEFAULT true CHECK (id),
+  count bigint NOT NULL
+);
+
+UPDATE quotes
+SET draw_position = numbered.draw_position
+FROM (
+  SELECT id, row_number() OVER (ORDER BY inserted_at, id) - 1 AS draw_position
+  FROM quotes
+  WHERE deleted_at IS NULL
+) AS numbered
+WHERE quotes.id = numbered.id;
+
+INSERT INTO live_quotes (count) SELECT count(*) FROM quotes WHERE deleted_at IS NULL;
+
+CREATE UNIQUE INDEX quotes_draw_position_key ON quotes (draw_position) WHERE deleted_at IS NULL;
+
+CREATE FUNCTION number_live_quote() RETURNS trigger AS $$
+BEGIN
+  UPDATE live_quotes SET count = count + 1 RETURNING count - 1 INTO NEW.draw_position;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Once quotes have left, hands the numbers they freed below the new count
+-- to the live quotes numbered past it. Statement triggers run it, since a
+-- statement may take out the last quotes along with the ones they would
+-- move into.
+CREATE FUNCTION renumber_live_quotes(freed bigint[]) RETURNS void AS $$
+DECLARE
+  live bigint;
+BEGIN
+  IF cardinality(freed) = 0 THEN
+    RETURN;
+  END IF;
+  UPDATE live_quotes SET count = count - cardinality(freed) RETURNING count INTO live;
+  UPDATE quotes
+  SET draw_position = gaps.draw_position
+  FROM (
+    SELECT draw_position, row_number() OVER (ORDER BY draw_position) AS n
+    FROM unnest(freed) AS draw_position
+    WHERE draw_position < live
+  ) AS gaps
+  JOIN (
+    SELECT id, row_number() OVER (ORDER BY draw_position) AS n
+    FROM quotes
+    WHERE deleted_at IS NULL AND draw_position >= live
+  ) AS movers ON movers.n = gaps.n
+  WHERE quotes.id = movers.id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE FUNCTION renumber_trashed_quotes() RETURNS trigger AS $$
+BEGIN
+  PERFORM renumber_live_quotes(ARRAY(
+    SELECT old_quotes.draw_position
+    FROM old_quotes JOIN new_quotes ON new_quotes.id = old_quotes.id
+    WHERE old_quotes.deleted_at IS NULL AND new_quotes.deleted_at IS NOT NULL
+  ));
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE FUNCTION renumber_deleted_quotes() RETURNS trigger AS $$
+BEGIN
+  PERFORM renumber_live_quotes(ARRAY(
+    SELECT draw_position FROM old_quotes WHERE deleted_at IS NULL
+  ));
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER quotes_number_on_insert
+  BEFORE INSERT ON quotes
+  FOR EACH ROW
+  WHEN (NEW.deleted_at IS NULL)
+  EXECUTE FUNCTION number_live_quote();
+
+CREATE TRIGGER quotes_number_on_restore
+  BEFORE UPDATE ON quotes
+  FOR EACH ROW
+  WHEN (OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL)
+  EXECUTE FUNCTION number_live_quote();
+
+CREATE TRIGGER quotes_renumber_on_trash
+  AFTER UPDATE ON quotes
+  REFERENCING OLD TABLE AS old_quotes NEW TABLE AS new_quotes
+  FOR EACH STATEMENT
+  EXECUTE FUNCTION renumber_trashed_quotes();
+
+CREATE TRIGGER quotes_renumber_on_delete
+  AFTER DELETE ON quotes
+  REFERENCING OLD TABLE AS old_quotes
+  FOR EACH STATEMENT
+  EXECUTE FUNCTION renumber_deleted_quotes();
+
+-- The quote of each day, kept from the first time the day is asked for.
+CREATE TABLE daily_quotes (
+  day date PRIMARY KEY,
+  quote_id UUID NOT NULL REFERENCES quotes (id) ON DELETE CASCADE
+);
+
+CREATE INDEX daily_quotes_quote_id_idx ON daily_quotes (quote_id);
@@ -0,0 +1,478 @@
+use crate::error::AppError;
+use crate::metrics;
+use crate::telemetry::Observe;
+use axum::extract::{self, Request};
+use axum::http;
+use axum::middleware::Next;
+use axum::response::Response;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use rand::RngCore;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, PgExecutor, PgPool};
+use std::sync::Arc;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+const KEY_PREFIX: &str = "qk_";
+const DEFAULT_KEY_SCOPES: &str = "quotes:read quotes:write";
+
+/// Access levels, each of which includes the ones before it.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Scope {
+    Read,
+    Write,
+    Admin,
+}
+
+impl Scope {
+    fn name(self) -> &'static str {
+        match self {
+            Scope::Read => "quotes:read",
+            Scope::Write => "quotes:write",
+            Scope::Admin => "quotes:admin",
+        }
+    }
+
+    /// The known scopes in a space-separated list; others are ignored.
+    fn parse_list(scopes: &str) -> Vec<Scope> {
+        scopes
+            .split_whitespace()
+            .filter_map(|name| {
+                [Scope::Read, Scope::Write, Scope::Admin]
+                    .into_iter()
+                    .find(|scope| scope.name() == name)
+            })
+            .collect()
+    }
+}
+
+/// Who is making a request, as established by `authenticate`.
+#[derive(Clone, Debug)]
+pub struct Caller {
+    /// The `sub` of a bearer token or `api-key:<id>`; `None` when anonymous.
+    pub subject: Option<String>,
+    pub scopes: Vec<Scope>,
+}
+
+impl Caller {
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.iter().any(|granted| *granted >= scope)
+    }
+
+    /// The owner whose quotes the caller may change, or `None` for admins,
+    /// who may change any. Anonymous callers own nothing.
+    pub fn owner(&self) -> Option<&str> {
+        if self.has_scope(Scope::Admin) {
+            None
+        } else {
+            Some(self.subject.as_deref().unwrap_or_default())
+        }
+    }
+}
+
+/// A caller known as `subject` and granted `scope`, for tests.
+#[cfg(test)]
+pub fn caller(subject: &str, scope: Scope) -> Caller {
+    Caller {
+        subject: Some(subject.to_string()),
+        scopes: vec![scope],
+    }
+}
+
+#[axum::async_trait]
+impl<S: Send + Sync> extract::FromRequestParts<S> for Caller {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut http::request::Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Caller>()
+            .cloned()
+            .ok_or(AppError::Unauthorized)
+    }
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    /// Space-separated, as in OAuth 2.0.
+    #[serde(default)]
+    scope: String,
+}
+
+enum JwtKeys {
+    Secret(DecodingKey),
+    Jwks(JwkSet),
+}
+
+/// How requests are authenticated, read from the environment once.
+#[derive(Clone)]
+pub struct AuthConfig {
+    jwt: Option<Arc<JwtKeys>>,
+    audience: Option<String>,
+    issuer: Option<String>,
+    /// Whether anonymous callers get `quotes:read`.
+    public_reads: bool,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            jwt: None,
+            audience: None,
+            issuer: None,
+            public_reads: true,
+        }
+    }
+}
+
+impl AuthConfig {
+    /// Verifies bearer tokens against the HS256 secret in `JWT_SECRET` or the
+    /// keys in the JWKS file at `JWT_JWKS_PATH`, checking `JWT_AUDIENCE` and
+    /// `JWT_ISSUER` when set. `PUBLIC_READS=false` makes reads need a scope too.
+    pub fn from_env() -> Self {
+        let jwt = match (std::env::var("JWT_SECRET"), std::env::var("JWT_JWKS_PATH")) {
+            (Ok(secret), _) => Some(JwtKeys::Secret(DecodingKey::from_secret(secret.as_bytes()))),
+            (_, Ok(path)) => {
+                let jwks = std::fs::read_to_string(&path)
+                    .unwrap_or_else(|err| panic!("cannot read JWT_JWKS_PATH {}: {}", path, err));
+                Some(JwtKeys::Jwks(
+                    serde_json::from_str(&jwks).expect("JWT_JWKS_PATH must hold a JWK set"),
+                ))
+            }
+            _ => None,
+        };
+        Self {
+            jwt: jwt.map(Arc::new),
+            audience: std::env::var("JWT_AUDIENCE").ok(),
+            issuer: std::env::var("JWT_ISSUER").ok(),
+            public_reads: std::env::var("PUBLIC_READS")
+                .map(|value| value != "false")
+                .unwrap_or(true),
+        }
+    }
+
+    fn verify(&self, token: &str) -> Option<Claims> {
+        let (key, algorithm) = match self.jwt.as_deref()? {
+            JwtKeys::Secret(key) => (key.clone(), Algorithm::HS256),
+            JwtKeys::Jwks(jwks) => {
+                let header = jsonwebtoken::decode_header(token).ok()?;
+                let jwk = match &header.kid {
+                    Some(kid) => jwks.find(kid)?,
+                    None if jwks.keys.len() == 1 => &jwks.keys[0],
+                    None => return None,
+                };
+                // The key must belong to the family of `alg`, which rules
+                // out verifying an HMAC signature with a public key.
+                (DecodingKey::from_jwk(jwk).ok()?, header.alg)
+            }
+        };
+        let mut validation = Validation::new(algorithm);
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        jsonwebtoken::decode::<Claims>(token, &key, &validation)
+            .ok()
+            .map(|data| data.claims)
+    }
+}
+
+#[derive(FromRow)]
+pub struct ApiKey {
+    pub id: uuid::Uuid,
+    pub name: String,
+    scopes: String,
+}
+
+fn hash(key: &str) -> Vec<u8> {
+    Sha256::digest(key.as_bytes()).to_vec()
+}
+
+/// Stores a new key under `name` and returns it along with the key itself,
+/// which cannot be recovered later. Keys carry 256 random bits, so a plain
+/// SHA-256 hash is enough to keep them safe at rest.
+pub async fn issue_key(
+    executor: impl PgExecutor<'_>,
+    name: &str,
+    scopes: &str,
+) -> sqlx::Result<(ApiKey, String)> {
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let key = format!("{}{}", KEY_PREFIX, URL_SAFE_NO_PAD.encode(secret));
+    let api_key = sqlx::query_as::<_, ApiKey>(
+        r#"
+        INSERT INTO api_keys (id, name, key_hash, scopes, inserted_at)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, name, scopes
+        "#,
+    )
+    .bind(uuid::Uuid::new_v4())
+    .bind(name)
+    .bind(hash(&key))
+    .bind(scopes)
+    .bind(chrono::Utc::now())
+    .fetch_one(executor)
+    .observe_one("issue_key")
+    .await?;
+    Ok((api_key, key))
+}
+
+/// Revokes a key for good; returns `None` if there is no live key with this id.
+pub async fn revoke_key(
+    executor: impl PgExecutor<'_>,
+    id: uuid::Uuid,
+) -> sqlx::Result<Option<ApiKey>> {
+    sqlx::query_as::<_, ApiKey>(
+        r#"
+        UPDATE api_keys
+        SET revoked_at = $2
+        WHERE id = $1 AND revoked_at IS NULL
+        RETURNING id, name, scopes
+        "#,
+    )
+    .bind(id)
+    .bind(chrono::Utc::now())
+    .fetch_optional(executor)
+    .observe("revoke_key")
+    .await
+}
+
+/// Establishes the `Caller` from a key in `X-Api-Key` or a bearer token in
+/// `Authorization`, and records who it is on the request span. Requests
+/// without credentials are anonymous; invalid credentials are refused.
+pub async fn authenticate(
+    extract::State(pool): extract::State<PgPool>,
+    extract::State(config): extract::State<AuthConfig>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let headers = request.headers();
+    let api_key = headers
+        .get(API_KEY_HEADER)
+        .map(|value| value.to_str().map_err(|_| AppError::Unauthorized))
+        .transpose()?;
+    let bearer = headers
+        .get(http::header::AUTHORIZATION)
+        .map(|value| {
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .ok_or(AppError::Unauthorized)
+        })
+        .transpose()?;
+    let caller = if let Some(key) = api_key {
+        let mut conn = metrics::acquire(&pool).await?;
+        let api_key = sqlx::query_as::<_, ApiKey>(
+            "SELECT id, name, scopes FROM api_keys WHERE key_hash = $1 AND revoked_at IS NULL",
+        )
+        .bind(hash(key))
+        .fetch_optional(&mut *conn)
+        .observe("find_api_key")
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+        tracing::Span::current().record("api_key_id", tracing::field::display(api_key.id));
+        Caller {
+            subject: Some(format!("api-key:{}", api_key.id)),
+            scopes: Scope::parse_list(&api_key.scopes),
+        }
+    } else if let Some(token) = bearer {
+        let claims = config.verify(token).ok_or(AppError::Unauthorized)?;
+        Caller {
+            subject: Some(claims.sub),
+            scopes: Scope::parse_list(&claims.scope),
+        }
+    } else {
+        Caller {
+            subject: None,
+            scopes: if config.public_reads {
+                vec![Scope::Read]
+            } else {
+                vec![]
+            },
+        }
+    };
+    if let Some(subject) = &caller.subject {
+        tracing::Span::current().record("subject", subject.as_str());
+    }
+    request.extensions_mut().insert(caller);
+    Ok(next.run(request).await)
+}
+
+/// Route layer that lets through only callers granted `scope`. Anonymous
+/// callers are told to authenticate, the others that they lack the scope.
+pub async fn require_scope(
+    extract::State(scope): extract::State<Scope>,
+    caller: Caller,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if caller.has_scope(scope) {
+        Ok(next.run(request).await)
+    } else if caller.subject.is_none() {
+        Err(AppError::Unauthorized)
+    } else {
+        Err(AppError::Forbidden(format!(
+            "requires the {} scope",
+            scope.name()
+        )))
+    }
+}
+
+/// Runs the `issue-key <name> [scopes]` and `revoke-key <id>` admin commands.
+pub async fn run_command(pool: &PgPool, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    match args {
+        [command, name, scopes @ ..] if command == "issue-key" && scopes.len() <= 1 => {
+            let scopes = scopes.first().map_or(DEFAULT_KEY_SCOPES, String::as_str);
+            let (api_key, key) = issue_key(pool, name, scopes).await?;
+            println!(
+                "issued key {} for {} with scopes {}",
+                api_key.id, api_key.name, api_key.scopes
+            );
+            println!("{}", key);
+        }
+        [command, id] if command == "revoke-key" => {
+            match revoke_key(pool, uuid::Uuid::parse_str(id)?).await? {
+                Some(api_key) => println!("revoked key {} of {}", api_key.id, api_key.name),
+                None => return Err(format!("no live key with id {}", id).into()),
+            }
+        }
+        _ => {
+            return Err(
+                "usage: quotes [issue-key <name> [\"<scope> ...\"] | revoke-key <id>]".into(),
+            )
+        }
+    }
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_authenticate(pool: PgPool) -> sqlx::Result<()> {
+    use axum::middleware::from_fn_with_state;
+    use axum::routing::{delete, get, post};
+    use tower::ServiceExt;
+
+    #[derive(Clone, extract::FromRef)]
+    struct State {
+        pool: PgPool,
+        config: AuthConfig,
+    }
+
+    let app = |config| {
+        let state = State {
+            pool: pool.clone(),
+            config,
+        };
+        axum::Router::new()
+            .route("/", get(|| async {}))
+            .route_layer(from_fn_with_state(Scope::Read, require_scope))
+            .merge(
+                axum::Router::new()
+                    .route("/", post(|| async {}))
+                    .route_layer(from_fn_with_state(Scope::Write, require_scope)),
+            )
+            .merge(
+                axum::Router::new()
+                    .route("/", delete(|| async {}))
+                    .route_layer(from_fn_with_state(Scope::Admin, require_scope)),
+            )
+            .route_layer(from_fn_with_state(state.clone(), authenticate))
+            .with_state(state)
+    };
+    let send = |app: &axum::Router, method, header: Option<(&str, String)>| {
+        let mut request = http::Request::builder().method(method).uri("/");
+        if let Some((name, value)) = header {
+            request = request.header(name, value);
+        }
+        let request = request.body(axum::body::Body::empty()).unwrap();
+        let res = app.clone().oneshot(request);
+        async { res.await.unwrap().status() }
+    };
+
+    // reads stay public, unless configured otherwise
+    let keys = app(AuthConfig::default());
+    assert_eq!(
+        send(&keys, http::Method::GET, None).await,
+        http::StatusCode::OK
+    );
+    assert_eq!(
+        send(&keys, http::Method::POST, None).await,
+        http::StatusCode::UNAUTHORIZED
+    );
+    let (api_key, key) = issue_key(&pool, "widget", DEFAULT_KEY_SCOPES).await?;
+    let header = || Some((API_KEY_HEADER, key.clone()));
+    assert_eq!(
+        send(&keys, http::Method::POST, header()).await,
+        http::StatusCode::OK
+    );
+    assert_eq!(
+        send(&keys, http::Method::DELETE, header()).await,
+        http::StatusCode::FORBIDDEN
+    );
+    let guess = Some((API_KEY_HEADER, "qk_guess".to_string()));
+    assert_eq!(
+        send(&keys, http::Method::POST, guess).await,
+        http::StatusCode::UNAUTHORIZED
+    );
+    assert!(revoke_key(&pool, api_key.id).await?.is_some());
+    assert_eq!(
+        send(&keys, http::Method::POST, header()).await,
+        http::StatusCode::UNAUTHORIZED
+    );
+
+    let secret = b"correct horse battery staple";
+    let token = |secret: &[u8], scope: &str| {
+        let claims = serde_json::json!({
+            "sub": "alice",
+            "scope": scope,
+            "exp": chrono::Utc::now().timestamp() + 60,
+        });
+        let key = jsonwebtoken::EncodingKey::from_secret(secret);
+        let token = jsonwebtoken::encode(&jsonwebtoken::Header::default(), &claims, &key);
+        Some(("authorization", format!("Bearer {}", token.unwrap())))
+    };
+    let jwks = serde_json::json!({
+        "keys": [{"kty": "oct", "kid": "1", "k": URL_SAFE_NO_PAD.encode(secret)}]
+    });
+    for keys in [
+        JwtKeys::Secret(DecodingKey::from_secret(secret)),
+        JwtKeys::Jwks(serde_json::from_value(jwks.clone()).unwrap()),
+    ] {
+        let tokens = app(AuthConfig {
+            jwt: Some(Arc::new(keys)),
+            public_reads: false,
+            ..Default::default()
+        });
+        assert_eq!(
+            send(&tokens, http::Method::GET, None).await,
+            http::StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(&tokens, http::Method::GET, token(secret, "quotes:read")).await,
+            http::StatusCode::OK
+        );
+        assert_eq!(
+            send(&tokens, http::Method::POST, token(secret, "quotes:read")).await,
+            http::StatusCode::FORBIDDEN
+        );
+        // admin includes every other scope
+        assert_eq!(
+            send(&tokens, http::Method::POST, token(secret, "quotes:admin")).await,
+            http::StatusCode::OK
+        );
+        assert_eq!(
+            send(&tokens, http::Method::GET, token(b"wrong", "quotes:admin")).await,
+            http::StatusCode::UNAUTHORIZED
+        );
+    }
+    Ok(())
+}
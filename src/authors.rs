@@ -0,0 +1,236 @@
+use crate::error::{self, AppError};
+use crate::handlers::{self, ListQuotes, Quote};
+use crate::metrics;
+use crate::pagination::{self, NameCursor, Page};
+use crate::telemetry::Observe;
+use crate::validation::{
+    FieldError, Limits, ValidJson, ValidPath, ValidQuery, Validate, Validator,
+};
+use axum::{extract, http};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+
+#[derive(Serialize, FromRow)]
+pub struct Author {
+    id: uuid::Uuid,
+    name: String,
+    inserted_at: chrono::DateTime<chrono::Utc>,
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateAuthor {
+    name: String,
+}
+
+impl Validate for CreateAuthor {
+    fn validate(&mut self, limits: &Limits) -> Result<(), Vec<FieldError>> {
+        Validator::default()
+            .text("name", &mut self.name, limits.max_author_length)
+            .finish()
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ListAuthors {
+    limit: Option<i64>,
+    cursor: Option<String>,
+}
+
+pub async fn create_author(
+    extract::State(pool): extract::State<PgPool>,
+    ValidJson(payload): ValidJson<CreateAuthor>,
+) -> Result<(http::StatusCode, axum::Json<Author>), AppError> {
+    let now = chrono::Utc::now();
+    let mut conn = metrics::acquire(&pool).await?;
+    let author = sqlx::query_as::<_, Author>(
+        r#"
+        INSERT INTO authors (id, name, inserted_at, updated_at)
+        VALUES ($1, $2, $3, $3)
+        RETURNING *
+        "#,
+    )
+    .bind(uuid::Uuid::new_v4())
+    .bind(&payload.name)
+    .bind(now)
+    .fetch_one(&mut *conn)
+    .observe_one("create_author")
+    .await?;
+    Ok((http::StatusCode::CREATED, axum::Json(author)))
+}
+
+pub async fn read_authors(
+    extract::State(pool): extract::State<PgPool>,
+    ValidQuery(params): ValidQuery<ListAuthors>,
+) -> Result<axum::Json<Page<Author>>, AppError> {
+    let limit = pagination::page_size(params.limit);
+    let cursor = match params.cursor.as_deref() {
+        Some(raw) => Some(
+            NameCursor::decode(raw).ok_or_else(|| AppError::InvalidQuery {
+                param: Some("cursor"),
+                message: "cursor is malformed".to_string(),
+            })?,
+        ),
+        None => None,
+    };
+    let mut conn = metrics::acquire(&pool).await?;
+    let authors = sqlx::query_as::<_, Author>(
+        r#"
+        SELECT * FROM authors
+        WHERE $1::varchar IS NULL OR (lower(name), id) > (lower($1), $2)
+        ORDER BY lower(name), id
+        LIMIT $3
+        "#,
+    )
+    .bind(cursor.as_ref().map(|cursor| &cursor.name))
+    .bind(cursor.as_ref().map(|cursor| cursor.id))
+    .bind(limit + 1)
+    .fetch_all(&mut *conn)
+    .observe("list_authors")
+    .await?;
+    Ok(axum::Json(Page::from_rows(authors, limit, |author| {
+        NameCursor {
+            id: author.id,
+            name: author.name.clone(),
+        }
+        .encode()
+    })))
+}
+
+pub async fn read_author(
+    extract::State(pool): extract::State<PgPool>,
+    ValidPath(id): ValidPath<uuid::Uuid>,
+) -> Result<axum::Json<Author>, AppError> {
+    let mut conn = metrics::acquire(&pool).await?;
+    sqlx::query_as::<_, Author>("SELECT * FROM authors WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .observe("read_author")
+        .await?
+        .map(axum::Json)
+        .ok_or(AppError::NotFound)
+}
+
+pub async fn update_author(
+    extract::State(pool): extract::State<PgPool>,
+    ValidPath(id): ValidPath<uuid::Uuid>,
+    ValidJson(payload): ValidJson<CreateAuthor>,
+) -> Result<axum::Json<Author>, AppError> {
+    let mut conn = metrics::acquire(&pool).await?;
+    sqlx::query_as::<_, Author>(
+        r#"
+        UPDATE authors
+        SET name = $1, updated_at = $2
+        WHERE id = $3
+        RETURNING *
+        "#,
+    )
+    .bind(&payload.name)
+    .bind(chrono::Utc::now())
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .observe("update_author")
+    .await?
+    .map(axum::Json)
+    .ok_or(AppError::NotFound)
+}
+
+pub async fn delete_author(
+    extract::State(pool): extract::State<PgPool>,
+    ValidPath(id): ValidPath<uuid::Uuid>,
+) -> Result<axum::Json<Author>, AppError> {
+    let mut conn = metrics::acquire(&pool).await?;
+    let res = sqlx::query_as::<_, Author>("DELETE FROM authors WHERE id = $1 RETURNING *")
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .observe("delete_author")
+        .await;
+    match res {
+        Ok(Some(author)) => Ok(axum::Json(author)),
+        Ok(None) => Err(AppError::NotFound),
+        Err(err) if error::has_code(&err, error::FOREIGN_KEY_VIOLATION) => Err(AppError::InUse(
+            "the author is still credited with books or quotes",
+        )),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Lists the quotes credited to an author, either directly or through the
+/// books they wrote.
+pub async fn read_author_quotes(
+    extract::State(pool): extract::State<PgPool>,
+    ValidPath(id): ValidPath<uuid::Uuid>,
+    mut params: ListQuotes,
+) -> Result<axum::Json<Page<Quote>>, AppError> {
+    let mut conn = metrics::acquire(&pool).await?;
+    let exists =
+        sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM authors WHERE id = $1)")
+            .bind(id)
+            .fetch_one(&mut *conn)
+            .observe_one("author_exists")
+            .await?;
+    if !exists {
+        return Err(AppError::NotFound);
+    }
+    params.author_id = Some(id);
+    handlers::list_quotes(&mut conn, params, false)
+        .await
+        .map(axum::Json)
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_author_quotes(pool: PgPool) -> sqlx::Result<()> {
+    let res = create_author(
+        extract::State(pool.clone()),
+        ValidJson(CreateAuthor {
+            name: "J. R. R. Tolkien".to_string(),
+        }),
+    )
+    .await;
+    let (status, axum::Json(author)) = res.unwrap();
+    assert_eq!(status, http::StatusCode::CREATED);
+    let res = read_author_quotes(
+        extract::State(pool.clone()),
+        ValidPath(author.id),
+        ListQuotes::default(),
+    )
+    .await;
+    assert!(res.unwrap().0.data.is_empty());
+    // writing the book credits the author with its quotes
+    sqlx::query("UPDATE books SET author_id = $1")
+        .bind(author.id)
+        .execute(&pool)
+        .await?;
+    let res = read_author_quotes(
+        extract::State(pool.clone()),
+        ValidPath(author.id),
+        ListQuotes::default(),
+    )
+    .await;
+    assert_eq!(res.unwrap().0.data.len(), 1);
+    // a quote credited directly is listed alongside
+    sqlx::query(
+        r#"
+        INSERT INTO quotes (id, book, book_id, author_id, quote, inserted_at, updated_at)
+        SELECT $1, title, id, $2, 'The road goes ever on and on.', now(), now() FROM books
+        "#,
+    )
+    .bind(uuid::Uuid::new_v4())
+    .bind(author.id)
+    .execute(&pool)
+    .await?;
+    let res = read_author_quotes(
+        extract::State(pool.clone()),
+        ValidPath(author.id),
+        ListQuotes::default(),
+    )
+    .await;
+    assert_eq!(res.unwrap().0.data.len(), 2);
+    // an author with books cannot be deleted
+    let res = delete_author(extract::State(pool), ValidPath(author.id)).await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::CONFLICT)
+    );
+    Ok(())
+}
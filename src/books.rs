@@ -0,0 +1,500 @@
+use crate::auth::Caller;
+use crate::error::{self, AppError};
+use crate::handlers::{self, ListQuotes, Quote};
+use crate::metrics;
+use crate::pagination::{self, NameCursor, Page};
+use crate::telemetry::Observe;
+use crate::validation::{
+    FieldError, Limits, ValidJson, ValidPath, ValidQuery, Validate, Validator,
+};
+use axum::{extract, http};
+use chrono::Datelike;
+use serde::{Deserialize, Serialize};
+use sqlx::{Connection, FromRow, PgExecutor, PgPool};
+
+#[derive(Serialize, FromRow)]
+pub struct Book {
+    id: uuid::Uuid,
+    title: String,
+    author_id: Option<uuid::Uuid>,
+    isbn: Option<String>,
+    published_year: Option<i32>,
+    inserted_at: chrono::DateTime<chrono::Utc>,
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateBook {
+    title: String,
+    author_id: Option<uuid::Uuid>,
+    isbn: Option<String>,
+    published_year: Option<i32>,
+}
+
+impl Validate for CreateBook {
+    fn validate(&mut self, limits: &Limits) -> Result<(), Vec<FieldError>> {
+        let this_year = chrono::Utc::now().year();
+        Validator::default()
+            .text("title", &mut self.title, limits.max_book_length)
+            .optional_isbn("isbn", &mut self.isbn)
+            .optional_range("published_year", self.published_year, -3000..=this_year)
+            .finish()
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ListBooks {
+    limit: Option<i64>,
+    cursor: Option<String>,
+}
+
+/// Turns a violation of the unique title or ISBN of a book into a conflict
+/// naming the book that is already stored, and a dangling `author_id` into a
+/// validation error; any other error is passed through.
+async fn constraint_violation(
+    executor: impl PgExecutor<'_>,
+    payload: &CreateBook,
+    err: sqlx::Error,
+) -> AppError {
+    if error::has_code(&err, error::FOREIGN_KEY_VIOLATION) {
+        return AppError::Validation(vec![FieldError {
+            field: "author_id",
+            message: "must refer to an existing author".to_string(),
+        }]);
+    }
+    if !error::has_code(&err, error::UNIQUE_VIOLATION) {
+        return err.into();
+    }
+    let existing = sqlx::query_scalar::<_, uuid::Uuid>(
+        "SELECT id FROM books WHERE lower(title) = lower($1) OR isbn = $2 LIMIT 1",
+    )
+    .bind(&payload.title)
+    .bind(&payload.isbn)
+    .fetch_optional(executor)
+    .observe("find_conflicting_book")
+    .await;
+    match existing {
+        Ok(Some(existing_id)) => AppError::Conflict { existing_id },
+        Ok(None) => err.into(),
+        Err(lookup_err) => lookup_err.into(),
+    }
+}
+
+/// Turns a violation of the unique title and text of a live quote, met when a
+/// rename would spell two quotes of the book that only differed in the case
+/// or whitespace of their title the same way, into a conflict naming one of
+/// them, preferably the one already spelled like `title`.
+async fn duplicate_quote(
+    executor: impl PgExecutor<'_>,
+    book_id: uuid::Uuid,
+    title: &str,
+    err: sqlx::Error,
+) -> AppError {
+    if !error::has_code(&err, error::UNIQUE_VIOLATION) {
+        return err.into();
+    }
+    let existing = sqlx::query_scalar::<_, uuid::Uuid>(
+        r#"
+        SELECT id FROM quotes AS existing
+        WHERE book_id = $1 AND deleted_at IS NULL AND EXISTS (
+            SELECT 1 FROM quotes
+            WHERE book_id = $1 AND quote = existing.quote AND id <> existing.id
+                AND deleted_at IS NULL
+        )
+        ORDER BY book = $2 DESC, inserted_at, id
+        LIMIT 1
+        "#,
+    )
+    .bind(book_id)
+    .bind(title)
+    .fetch_optional(executor)
+    .observe("find_conflicting_quote")
+    .await;
+    match existing {
+        Ok(Some(existing_id)) => AppError::Conflict { existing_id },
+        Ok(None) => err.into(),
+        Err(lookup_err) => lookup_err.into(),
+    }
+}
+
+pub async fn create_book(
+    extract::State(pool): extract::State<PgPool>,
+    ValidJson(payload): ValidJson<CreateBook>,
+) -> Result<(http::StatusCode, axum::Json<Book>), AppError> {
+    let now = chrono::Utc::now();
+    let mut conn = metrics::acquire(&pool).await?;
+    let res = sqlx::query_as::<_, Book>(
+        r#"
+        INSERT INTO books (id, title, author_id, isbn, published_year, inserted_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $6)
+        RETURNING *
+        "#,
+    )
+    .bind(uuid::Uuid::new_v4())
+    .bind(&payload.title)
+    .bind(payload.author_id)
+    .bind(&payload.isbn)
+    .bind(payload.published_year)
+    .bind(now)
+    .fetch_one(&mut *conn)
+    .observe_one("create_book")
+    .await;
+    match res {
+        Ok(book) => Ok((http::StatusCode::CREATED, axum::Json(book))),
+        Err(err) => Err(constraint_violation(&mut *conn, &payload, err).await),
+    }
+}
+
+pub async fn read_books(
+    extract::State(pool): extract::State<PgPool>,
+    ValidQuery(params): ValidQuery<ListBooks>,
+) -> Result<axum::Json<Page<Book>>, AppError> {
+    let limit = pagination::page_size(params.limit);
+    let cursor = match params.cursor.as_deref() {
+        Some(raw) => Some(
+            NameCursor::decode(raw).ok_or_else(|| AppError::InvalidQuery {
+                param: Some("cursor"),
+                message: "cursor is malformed".to_string(),
+            })?,
+        ),
+        None => None,
+    };
+    let mut conn = metrics::acquire(&pool).await?;
+    let books = sqlx::query_as::<_, Book>(
+        r#"
+        SELECT * FROM books
+        WHERE $1::varchar IS NULL OR (lower(title), id) > (lower($1), $2)
+        ORDER BY lower(title), id
+        LIMIT $3
+        "#,
+    )
+    .bind(cursor.as_ref().map(|cursor| &cursor.name))
+    .bind(cursor.as_ref().map(|cursor| cursor.id))
+    .bind(limit + 1)
+    .fetch_all(&mut *conn)
+    .observe("list_books")
+    .await?;
+    Ok(axum::Json(Page::from_rows(books, limit, |book| {
+        NameCursor {
+            id: book.id,
+            name: book.title.clone(),
+        }
+        .encode()
+    })))
+}
+
+pub async fn read_book(
+    extract::State(pool): extract::State<PgPool>,
+    ValidPath(id): ValidPath<uuid::Uuid>,
+) -> Result<axum::Json<Book>, AppError> {
+    let mut conn = metrics::acquire(&pool).await?;
+    sqlx::query_as::<_, Book>("SELECT * FROM books WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .observe("read_book")
+        .await?
+        .map(axum::Json)
+        .ok_or(AppError::NotFound)
+}
+
+/// Updates a book. A rename is written into its quotes too, so unless the
+/// caller added every one of them, only an admin may rename it.
+pub async fn update_book(
+    extract::State(pool): extract::State<PgPool>,
+    ValidPath(id): ValidPath<uuid::Uuid>,
+    caller: Caller,
+    ValidJson(payload): ValidJson<CreateBook>,
+) -> Result<axum::Json<Book>, AppError> {
+    let now = chrono::Utc::now();
+    let mut conn = metrics::acquire(&pool).await?;
+    let mut tx = conn.begin().await?;
+    if let Some(owner) = caller.owner() {
+        let others = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM quotes
+                WHERE book_id = $1 AND book <> $2 AND created_by IS DISTINCT FROM $3
+            )
+            "#,
+        )
+        .bind(id)
+        .bind(&payload.title)
+        .bind(owner)
+        .fetch_one(&mut *tx)
+        .observe_one("find_others_book_quotes")
+        .await?;
+        if others {
+            return Err(AppError::Forbidden(
+                "only an admin may rename a book with quotes added by others".to_string(),
+            ));
+        }
+    }
+    let res = sqlx::query_as::<_, Book>(
+        r#"
+        UPDATE books
+        SET title = $1, author_id = $2, isbn = $3, published_year = $4, updated_at = $5
+        WHERE id = $6
+        RETURNING *
+        "#,
+    )
+    .bind(&payload.title)
+    .bind(payload.author_id)
+    .bind(&payload.isbn)
+    .bind(payload.published_year)
+    .bind(now)
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .observe("update_book")
+    .await;
+    let book = match res {
+        Ok(Some(book)) => book,
+        Ok(None) => return Err(AppError::NotFound),
+        Err(err) => {
+            tx.rollback().await?;
+            return Err(constraint_violation(&mut *conn, &payload, err).await);
+        }
+    };
+    // Quotes carry the title of their book, so a rename has to reach them too.
+    let res = sqlx::query(
+        r#"
+        UPDATE quotes SET book = $1, updated_by = $4, updated_at = $2
+        WHERE book_id = $3 AND book <> $1
+        "#,
+    )
+    .bind(&book.title)
+    .bind(now)
+    .bind(id)
+    .bind(&caller.subject)
+    .execute(&mut *tx)
+    .observe("rename_book_quotes")
+    .await;
+    if let Err(err) = res {
+        tx.rollback().await?;
+        return Err(duplicate_quote(&mut *conn, id, &book.title, err).await);
+    }
+    tx.commit().await?;
+    Ok(axum::Json(book))
+}
+
+pub async fn delete_book(
+    extract::State(pool): extract::State<PgPool>,
+    ValidPath(id): ValidPath<uuid::Uuid>,
+) -> Result<axum::Json<Book>, AppError> {
+    let mut conn = metrics::acquire(&pool).await?;
+    let res = sqlx::query_as::<_, Book>("DELETE FROM books WHERE id = $1 RETURNING *")
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .observe("delete_book")
+        .await;
+    match res {
+        Ok(Some(book)) => Ok(axum::Json(book)),
+        Ok(None) => Err(AppError::NotFound),
+        Err(err) if error::has_code(&err, error::FOREIGN_KEY_VIOLATION) => Err(AppError::InUse(
+            "the book still has quotes, including ones in the trash",
+        )),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub async fn read_book_quotes(
+    extract::State(pool): extract::State<PgPool>,
+    ValidPath(id): ValidPath<uuid::Uuid>,
+    mut params: ListQuotes,
+) -> Result<axum::Json<Page<Quote>>, AppError> {
+    let mut conn = metrics::acquire(&pool).await?;
+    let exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM books WHERE id = $1)")
+        .bind(id)
+        .fetch_one(&mut *conn)
+        .observe_one("book_exists")
+        .await?;
+    if !exists {
+        return Err(AppError::NotFound);
+    }
+    params.book_id = Some(id);
+    handlers::list_quotes(&mut conn, params, false)
+        .await
+        .map(axum::Json)
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_create_book(pool: PgPool) -> sqlx::Result<()> {
+    let author_id = uuid::Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO authors (id, name, inserted_at, updated_at) VALUES ($1, $2, now(), now())",
+    )
+    .bind(author_id)
+    .bind("Frank Herbert")
+    .execute(&pool)
+    .await?;
+    let res = create_book(
+        extract::State(pool.clone()),
+        ValidJson(CreateBook {
+            title: "Dune".to_string(),
+            author_id: Some(author_id),
+            isbn: Some("9780441172719".to_string()),
+            published_year: Some(1965),
+        }),
+    )
+    .await;
+    let (status, axum::Json(book)) = res.unwrap();
+    assert_eq!(status, http::StatusCode::CREATED);
+    let res = read_book(extract::State(pool.clone()), ValidPath(book.id)).await;
+    assert_eq!(res.unwrap().0.author_id, Some(author_id));
+    // titles are unique regardless of case
+    let res = create_book(
+        extract::State(pool.clone()),
+        ValidJson(CreateBook {
+            title: "the hobbit".to_string(),
+            author_id: None,
+            isbn: None,
+            published_year: None,
+        }),
+    )
+    .await;
+    match res {
+        Err(AppError::Conflict { existing_id }) => assert_eq!(
+            existing_id,
+            uuid::Uuid::parse_str("b1eebc99-9c0b-4ef8-bb6d-6bb9bd380a22").unwrap()
+        ),
+        _ => panic!("expected a conflict"),
+    }
+    let res = read_books(
+        extract::State(pool),
+        ValidQuery(ListBooks {
+            limit: Some(1),
+            cursor: None,
+        }),
+    )
+    .await;
+    let page = res.unwrap().0;
+    assert_eq!(page.data[0].title, "Dune");
+    assert!(page.next_cursor.is_some());
+    Ok(())
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_book_quotes(pool: PgPool) -> sqlx::Result<()> {
+    let book_id = uuid::Uuid::parse_str("b1eebc99-9c0b-4ef8-bb6d-6bb9bd380a22").unwrap();
+    let res = read_book_quotes(
+        extract::State(pool.clone()),
+        ValidPath(book_id),
+        ListQuotes::default(),
+    )
+    .await;
+    assert_eq!(res.unwrap().0.data.len(), 1);
+    // renaming a book renames it on its quotes, which takes an admin
+    // unless they were all added by the caller
+    let rename = |caller| {
+        update_book(
+            extract::State(pool.clone()),
+            ValidPath(book_id),
+            caller,
+            ValidJson(CreateBook {
+                title: "The Hobbit, or There and Back Again".to_string(),
+                author_id: None,
+                isbn: None,
+                published_year: Some(1937),
+            }),
+        )
+    };
+    let res = rename(crate::auth::caller("alice", crate::auth::Scope::Write)).await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::FORBIDDEN)
+    );
+    let res = rename(crate::auth::caller("admin", crate::auth::Scope::Admin)).await;
+    assert!(res.is_ok());
+    let res = read_book_quotes(
+        extract::State(pool.clone()),
+        ValidPath(book_id),
+        ListQuotes::default(),
+    )
+    .await;
+    let quotes = serde_json::to_value(res.unwrap().0.data).unwrap();
+    assert_eq!(quotes[0]["book"], "The Hobbit, or There and Back Again");
+    // a book with quotes cannot be deleted
+    let res = delete_book(extract::State(pool), ValidPath(book_id)).await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::CONFLICT)
+    );
+    Ok(())
+}
+
+#[sqlx::test(migrations = false)]
+async fn test_books_migration(pool: PgPool) -> sqlx::Result<()> {
+    use sqlx::Executor;
+
+    const BOOKS_MIGRATION: i64 = 20231220090000;
+    let migrator = sqlx::migrate!();
+    let (before, after): (Vec<_>, Vec<_>) = migrator
+        .iter()
+        .partition(|migration| migration.version < BOOKS_MIGRATION);
+    for migration in before {
+        pool.execute(&*migration.sql).await?;
+    }
+    let ids: Vec<uuid::Uuid> = (0..4).map(|_| uuid::Uuid::new_v4()).collect();
+    for (id, (book, quote, deleted)) in ids.iter().zip([
+        ("The Hobbit", "Q1", false),
+        ("the hobbit ", "Q2", false),
+        ("THE HOBBIT", "Q2", false),
+        ("the hobbit", "Q1", true),
+    ]) {
+        sqlx::query(
+            r#"
+            INSERT INTO quotes (id, book, quote, inserted_at, updated_at, deleted_at)
+            VALUES ($1, $2, $3, clock_timestamp(), now(), CASE WHEN $4 THEN now() END)
+            "#,
+        )
+        .bind(id)
+        .bind(book)
+        .bind(quote)
+        .bind(deleted)
+        .execute(&pool)
+        .await?;
+    }
+    // titles that only differ in case or whitespace make one book, and each
+    // live quote is spelled like it unless another one already is
+    for migration in after {
+        pool.execute(&*migration.sql).await?;
+    }
+    let books = sqlx::query_scalar::<_, String>("SELECT title FROM books")
+        .fetch_all(&pool)
+        .await?;
+    assert_eq!(books, ["The Hobbit"]);
+    let mut spelled = Vec::new();
+    for id in &ids {
+        spelled.push(
+            sqlx::query_scalar::<_, String>("SELECT book FROM quotes WHERE id = $1")
+                .bind(id)
+                .fetch_one(&pool)
+                .await?,
+        );
+    }
+    assert_eq!(
+        spelled,
+        ["The Hobbit", "The Hobbit", "THE HOBBIT", "The Hobbit"]
+    );
+
+    // a rename that would make the quotes left alone duplicates is a conflict
+    let book_id = sqlx::query_scalar::<_, uuid::Uuid>("SELECT id FROM books")
+        .fetch_one(&pool)
+        .await?;
+    let res = update_book(
+        extract::State(pool.clone()),
+        ValidPath(book_id),
+        crate::auth::caller("admin", crate::auth::Scope::Admin),
+        ValidJson(CreateBook {
+            title: "There and Back Again".to_string(),
+            author_id: None,
+            isbn: None,
+            published_year: None,
+        }),
+    )
+    .await;
+    match res {
+        Err(AppError::Conflict { existing_id }) => assert_eq!(existing_id, ids[1]),
+        _ => panic!("expected a conflict"),
+    }
+    Ok(())
+}
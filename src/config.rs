@@ -0,0 +1,15 @@
+use std::str::FromStr;
+
+/// Reads the number in the environment variable `name`, or `default` when
+/// it is not set. Anything else is a mistake in the configuration, which
+/// stops the service from starting.
+pub fn env_number<T: FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .unwrap_or_else(|_| panic!("{} must be a number", name))
+        })
+        .unwrap_or(default)
+}
@@ -0,0 +1,200 @@
+use crate::request_id;
+use crate::validation::FieldError;
+use axum::extract::rejection::JsonRejection;
+use axum::http::{self, header};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+
+/// SQLSTATE codes of the constraint violations the handlers report to clients.
+pub const UNIQUE_VIOLATION: &str = "23505";
+pub const FOREIGN_KEY_VIOLATION: &str = "23503";
+
+/// Whether `err` is a database error with the given SQLSTATE `code`.
+pub fn has_code(err: &sqlx::Error, code: &str) -> bool {
+    err.as_database_error()
+        .and_then(|db_err| db_err.code())
+        .is_some_and(|db_code| db_code == code)
+}
+
+/// Errors returned by the handlers, rendered as RFC 7807 problem details.
+#[derive(Debug)]
+pub enum AppError {
+    /// The request needs credentials, or came with invalid ones.
+    Unauthorized,
+    /// The caller is known but may not do this.
+    Forbidden(String),
+    NotFound,
+    /// The write would duplicate the resource with this id.
+    Conflict {
+        existing_id: uuid::Uuid,
+    },
+    /// The resource cannot be removed while others still refer to it.
+    InUse(&'static str),
+    /// The resource changed since the version named in `If-Match`.
+    PreconditionFailed,
+    /// The client spent its request budget; it may retry after this many seconds.
+    TooManyRequests {
+        retry_after: u64,
+    },
+    InvalidQuery {
+        param: Option<&'static str>,
+        message: String,
+    },
+    /// The request body is not JSON of the expected shape.
+    InvalidBody(JsonRejection),
+    Validation(Vec<FieldError>),
+    /// The operation succeeded or was never run, but its batch was rolled back.
+    RolledBack,
+    Database(sqlx::Error),
+}
+
+/// Body of an `application/problem+json` response.
+#[derive(Serialize, Debug)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: &'static str,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    param: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    existing_id: Option<uuid::Uuid>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+    /// The `X-Request-Id` of the request, to find it in the logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl AppError {
+    pub fn status(&self) -> http::StatusCode {
+        match self {
+            AppError::Unauthorized => http::StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => http::StatusCode::FORBIDDEN,
+            AppError::NotFound => http::StatusCode::NOT_FOUND,
+            AppError::Conflict { .. } | AppError::InUse(_) => http::StatusCode::CONFLICT,
+            AppError::PreconditionFailed => http::StatusCode::PRECONDITION_FAILED,
+            AppError::TooManyRequests { .. } => http::StatusCode::TOO_MANY_REQUESTS,
+            AppError::InvalidQuery { .. } => http::StatusCode::BAD_REQUEST,
+            AppError::InvalidBody(rejection) => rejection.status(),
+            AppError::Validation(_) => http::StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::RolledBack => http::StatusCode::FAILED_DEPENDENCY,
+            AppError::Database(_) => http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            AppError::Unauthorized => "Authentication required",
+            AppError::Forbidden(_) => "Access denied",
+            AppError::NotFound => "Resource not found",
+            AppError::Conflict { .. } => "Resource already exists",
+            AppError::InUse(_) => "Resource is still in use",
+            AppError::PreconditionFailed => "Resource was modified",
+            AppError::TooManyRequests { .. } => "Too many requests",
+            AppError::InvalidQuery { .. } => "Invalid query parameter",
+            AppError::InvalidBody(_) => "Invalid request body",
+            AppError::Validation(_) => "Validation failed",
+            AppError::RolledBack => "Operation rolled back",
+            AppError::Database(_) => "Internal server error",
+        }
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        AppError::Database(err)
+    }
+}
+
+impl AppError {
+    /// Renders the error as problem details, logging the cause of server errors.
+    pub fn into_problem(self) -> Problem {
+        let mut problem = Problem {
+            kind: "about:blank",
+            title: self.title(),
+            status: self.status().as_u16(),
+            detail: None,
+            param: None,
+            existing_id: None,
+            errors: Vec::new(),
+            request_id: None,
+        };
+        match self {
+            AppError::Unauthorized => {
+                problem.detail = Some(
+                    "a valid API key in X-Api-Key or bearer token in Authorization is required"
+                        .to_string(),
+                )
+            }
+            AppError::Forbidden(detail) => problem.detail = Some(detail),
+            AppError::NotFound | AppError::PreconditionFailed | AppError::RolledBack => {}
+            AppError::Conflict { existing_id } => {
+                problem.detail = Some(format!(
+                    "the same resource already exists as {}",
+                    existing_id
+                ));
+                problem.existing_id = Some(existing_id);
+            }
+            AppError::InvalidQuery { param, message } => {
+                problem.param = param;
+                problem.detail = Some(message);
+            }
+            AppError::InUse(detail) => problem.detail = Some(detail.to_string()),
+            AppError::TooManyRequests { retry_after } => {
+                problem.detail = Some(format!("try again in {} seconds", retry_after))
+            }
+            AppError::InvalidBody(rejection) => problem.detail = Some(rejection.body_text()),
+            AppError::Validation(errors) => problem.errors = errors,
+            // The cause stays in the logs, under the span of the request that hit it.
+            AppError::Database(err) => tracing::error!(error = %err, "database error"),
+        }
+        problem
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let retry_after = match self {
+            AppError::TooManyRequests { retry_after } => Some(retry_after),
+            _ => None,
+        };
+        let mut problem = self.into_problem();
+        problem.request_id = request_id::current();
+        let mut res = (
+            status,
+            [(header::CONTENT_TYPE, "application/problem+json")],
+            axum::Json(problem),
+        )
+            .into_response();
+        if let Some(retry_after) = retry_after {
+            res.headers_mut()
+                .insert(header::RETRY_AFTER, retry_after.into());
+        }
+        res
+    }
+}
+
+#[tokio::test]
+async fn test_problem_response() {
+    let res = AppError::InvalidQuery {
+        param: Some("sort"),
+        message: "cannot sort by unknown field `author`".to_string(),
+    }
+    .into_response();
+    assert_eq!(res.status(), http::StatusCode::BAD_REQUEST);
+    assert_eq!(
+        res.headers()[header::CONTENT_TYPE],
+        "application/problem+json"
+    );
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(problem["status"], 400);
+    assert_eq!(problem["param"], "sort");
+    assert_eq!(problem["detail"], "cannot sort by unknown field `author`");
+}
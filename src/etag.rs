@@ -0,0 +1,75 @@
+use axum::http::{self, header};
+
+type Timestamp = chrono::DateTime<chrono::Utc>;
+
+/// Strong entity tag for a quote, derived from its `updated_at`.
+pub fn etag(updated_at: Timestamp) -> http::HeaderValue {
+    http::HeaderValue::from_str(&format!("\"{}\"", updated_at.timestamp_micros()))
+        .expect("a quoted integer is a valid header value")
+}
+
+fn tags(headers: &http::HeaderMap, name: header::HeaderName) -> Option<Vec<String>> {
+    let mut values = headers.get_all(name).iter().peekable();
+    values.peek()?;
+    Some(
+        values
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|tag| tag.trim().to_string())
+            .collect(),
+    )
+}
+
+fn parse(tag: &str) -> Option<Timestamp> {
+    let micros: i64 = tag.strip_prefix('"')?.strip_suffix('"')?.parse().ok()?;
+    chrono::DateTime::from_timestamp(
+        micros.div_euclid(1_000_000),
+        micros.rem_euclid(1_000_000) as u32 * 1_000,
+    )
+}
+
+/// The `updated_at` values accepted by an `If-Match` header, or `None` when
+/// any version may be modified. Weak tags never match, as RFC 9110 requires.
+pub fn if_match(headers: &http::HeaderMap) -> Option<Vec<Timestamp>> {
+    let tags = tags(headers, header::IF_MATCH)?;
+    if tags.iter().any(|tag| tag == "*") {
+        return None;
+    }
+    Some(tags.iter().filter_map(|tag| parse(tag)).collect())
+}
+
+/// Whether an `If-None-Match` header matches the version at `updated_at`,
+/// meaning the client's copy is current.
+pub fn if_none_match(headers: &http::HeaderMap, updated_at: Timestamp) -> bool {
+    let current = etag(updated_at);
+    tags(headers, header::IF_NONE_MATCH).is_some_and(|tags| {
+        tags.iter()
+            .any(|tag| tag == "*" || current == tag.trim_start_matches("W/"))
+    })
+}
+
+#[test]
+fn test_conditional_headers() {
+    let updated_at = chrono::Utc::now();
+    let mut headers = http::HeaderMap::new();
+    assert_eq!(if_match(&headers), None);
+    assert!(!if_none_match(&headers, updated_at));
+
+    headers.insert(header::IF_NONE_MATCH, etag(updated_at));
+    assert!(if_none_match(&headers, updated_at));
+    assert!(!if_none_match(
+        &headers,
+        updated_at + chrono::Duration::seconds(1)
+    ));
+
+    headers.insert(
+        header::IF_MATCH,
+        http::HeaderValue::from_static("W/\"1\", \"2\""),
+    );
+    assert_eq!(
+        if_match(&headers),
+        Some(vec![chrono::DateTime::from_timestamp(0, 2_000).unwrap()])
+    );
+    headers.insert(header::IF_MATCH, http::HeaderValue::from_static("*"));
+    assert_eq!(if_match(&headers), None);
+}
@@ -1,3 +1,6 @@
-INSERT INTO quotes (id, book, quote, inserted_at, updated_at)
-VALUES ('a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11', 'The Hobbit', 'In a hole in the ground there lived a hobbit.', '2020-12-08 10:30:24.000000', '2020-12-08 10:30:24.000000');
+INSERT INTO books (id, title, inserted_at, updated_at)
+VALUES ('b1eebc99-9c0b-4ef8-bb6d-6bb9bd380a22', 'The Hobbit', '2020-12-08 10:30:24.000000', '2020-12-08 10:30:24.000000');
+
+INSERT INTO quotes (id, book_id, book, quote, inserted_at, updated_at)
+VALUES ('a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11', 'b1eebc99-9c0b-4ef8-bb6d-6bb9bd380a22', 'The Hobbit', 'In a hole in the ground there lived a hobbit.', '2020-12-08 10:30:24.000000', '2020-12-08 10:30:24.000000');
 
@@ -1,131 +1,1256 @@
+use crate::auth::Caller;
+use crate::error::{self, AppError, Problem};
+use crate::etag;
+use crate::metrics;
+use crate::pagination::{self, Page};
+use crate::telemetry::Observe;
+use crate::validation::{
+    self, FieldError, Limits, ValidJson, ValidPath, ValidQuery, Validate, Validator,
+};
+use axum::response::{IntoResponse, Response};
 use axum::{extract, http};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::SeedableRng;
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
+use sqlx::{Connection, FromRow, PgConnection, PgExecutor, PgPool};
+
+const MAX_BULK_OPERATIONS: usize = 1000;
+const MAX_TAGS: usize = 32;
+const MAX_TAG_LENGTH: usize = 64;
+/// Numbers a random pick tries before it counts the quotes that pass its
+/// filter instead. Each is an index lookup; a filter that a tenth of the
+/// quotes pass still falls back on fewer than one pick in five.
+const DRAWS: usize = 16;
+
+/// The columns of a `Quote`: its row in `quotes` followed by the names of
+/// its tags. Every statement that yields quotes selects or returns these.
+const QUOTE_COLUMNS: &str = r#"
+    quotes.*, ARRAY(
+        SELECT tags.name FROM quote_tags JOIN tags ON tags.id = quote_tags.tag_id
+        WHERE quote_tags.quote_id = quotes.id
+        ORDER BY tags.name
+    ) AS tags
+"#;
+
+/// The text of a quote escaped for HTML, so that the markup `ts_headline`
+/// adds around matches is the only markup in a snippet.
+const ESCAPED_QUOTE: &str =
+    "replace(replace(replace(quote, '&', '&amp;'), '<', '&lt;'), '>', '&gt;')";
+
+/// Finds the book titled `$1`, ignoring case, or adds it with id `$2` at
+/// time `$3`. Every statement that sets the book of a quote starts with it,
+/// so that differently typed titles end up on the same book.
+const UPSERT_BOOK: &str = r#"
+    WITH book AS (
+        INSERT INTO books (id, title, inserted_at, updated_at)
+        SELECT $2, $1::varchar, $3, $3 WHERE $1 IS NOT NULL
+        ON CONFLICT ((lower(title))) DO UPDATE SET title = books.title
+        RETURNING id, title
+    )
+"#;
 
 #[derive(Serialize, FromRow)]
 pub struct Quote {
     id: uuid::Uuid,
+    book_id: uuid::Uuid,
     book: String,
+    /// Set for quotes credited to someone other than the author of the book.
+    author_id: Option<uuid::Uuid>,
     quote: String,
+    tags: Vec<String>,
+    /// The caller that added the quote; `None` for quotes older than that.
+    created_by: Option<String>,
     inserted_at: chrono::DateTime<chrono::Utc>,
     updated_at: chrono::DateTime<chrono::Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deleted_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
-impl Quote {
-    fn new(book: String, quote: String) -> Self {
-        let now = chrono::Utc::now();
-        Self {
-            id: uuid::Uuid::new_v4(),
-            book,
-            quote,
-            inserted_at: now,
-            updated_at: now,
-        }
+/// A quote rendered as JSON together with its `ETag` header.
+pub struct Tagged(pub Quote);
+
+impl IntoResponse for Tagged {
+    fn into_response(self) -> Response {
+        let etag = etag::etag(self.0.updated_at);
+        ([(http::header::ETAG, etag)], axum::Json(self.0)).into_response()
     }
 }
 
 #[derive(Deserialize, Debug)]
 pub struct CreateQuote {
-    book: String,
-    quote: String,
+    pub(crate) book: String,
+    pub(crate) quote: String,
+    #[serde(default)]
+    pub(crate) author_id: Option<uuid::Uuid>,
+}
+
+impl Validate for CreateQuote {
+    fn validate(&mut self, limits: &Limits) -> Result<(), Vec<FieldError>> {
+        Validator::default()
+            .text("book", &mut self.book, limits.max_book_length)
+            .text("quote", &mut self.quote, limits.max_quote_length)
+            .finish()
+    }
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkMode {
+    #[default]
+    AllOrNothing,
+    BestEffort,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BulkOperation {
+    Create(CreateQuote),
+    Update {
+        id: uuid::Uuid,
+        #[serde(flatten)]
+        quote: CreateQuote,
+    },
+    Delete {
+        id: uuid::Uuid,
+    },
+}
+
+impl Validate for BulkOperation {
+    fn validate(&mut self, limits: &Limits) -> Result<(), Vec<FieldError>> {
+        match self {
+            BulkOperation::Create(quote) | BulkOperation::Update { quote, .. } => {
+                quote.validate(limits)
+            }
+            BulkOperation::Delete { .. } => Ok(()),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BulkRequest {
+    #[serde(default)]
+    mode: BulkMode,
+    operations: Vec<BulkOperation>,
+}
+
+/// Only the size of the batch is checked up front; each operation is
+/// validated on its own so its errors end up in its own result.
+impl Validate for BulkRequest {
+    fn validate(&mut self, _limits: &Limits) -> Result<(), Vec<FieldError>> {
+        let message = if self.operations.is_empty() {
+            "must contain at least one operation".to_string()
+        } else if self.operations.len() > MAX_BULK_OPERATIONS {
+            format!("must contain at most {} operations", MAX_BULK_OPERATIONS)
+        } else {
+            return Ok(());
+        };
+        Err(vec![FieldError {
+            field: "operations",
+            message,
+        }])
+    }
+}
+
+#[derive(Serialize)]
+pub struct BulkResult {
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quote: Option<Quote>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Problem>,
+}
+
+impl From<Result<(http::StatusCode, Quote), AppError>> for BulkResult {
+    fn from(res: Result<(http::StatusCode, Quote), AppError>) -> Self {
+        match res {
+            Ok((status, quote)) => Self {
+                status: status.as_u16(),
+                quote: Some(quote),
+                error: None,
+            },
+            Err(err) => Self {
+                status: err.status().as_u16(),
+                quote: None,
+                error: Some(err.into_problem()),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct BulkResponse {
+    committed: bool,
+    results: Vec<BulkResult>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct PatchQuote {
+    book: Option<String>,
+    quote: Option<String>,
+    /// `Some(None)` when the payload sets it to `null`, which clears it.
+    #[serde(default, deserialize_with = "present")]
+    author_id: Option<Option<uuid::Uuid>>,
+}
+
+/// Deserializes a field that is in the payload, even as `null`, to `Some`;
+/// with `#[serde(default)]`, only a field left out stays `None`.
+fn present<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+impl Validate for PatchQuote {
+    fn validate(&mut self, limits: &Limits) -> Result<(), Vec<FieldError>> {
+        Validator::default()
+            .optional_text("book", &mut self.book, limits.max_book_length)
+            .optional_text("quote", &mut self.quote, limits.max_quote_length)
+            .finish()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReplaceTags {
+    tags: Vec<String>,
+}
+
+impl Validate for ReplaceTags {
+    fn validate(&mut self, _limits: &Limits) -> Result<(), Vec<FieldError>> {
+        Validator::default()
+            .tags("tags", &mut self.tags, MAX_TAGS, MAX_TAG_LENGTH)
+            .finish()
+    }
+}
+
+/// How the tags of a `tag=` filter combine.
+#[derive(Deserialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TagMatch {
+    /// The quote has at least one of the tags.
+    #[default]
+    Any,
+    /// The quote has every one of the tags.
+    All,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ListQuotes {
+    limit: Option<i64>,
+    cursor: Option<String>,
+    pub(crate) book_id: Option<uuid::Uuid>,
+    pub(crate) author_id: Option<uuid::Uuid>,
+    book: Option<String>,
+    quote: Option<String>,
+    /// Comma-separated tag names.
+    tag: Option<String>,
+    tag_match: Option<TagMatch>,
+    inserted_after: Option<chrono::DateTime<chrono::Utc>>,
+    inserted_before: Option<chrono::DateTime<chrono::Utc>>,
+    updated_after: Option<chrono::DateTime<chrono::Utc>>,
+    updated_before: Option<chrono::DateTime<chrono::Utc>>,
+    sort: Option<String>,
+    /// Only the quotes the caller added.
+    #[serde(default)]
+    mine: bool,
+    #[serde(skip)]
+    created_by: Option<String>,
+}
+
+#[axum::async_trait]
+impl<S: Send + Sync> extract::FromRequestParts<S> for ListQuotes {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let ValidQuery(mut params) =
+            ValidQuery::<ListQuotes>::from_request_parts(parts, state).await?;
+        if params.mine {
+            // Anonymous callers have no quotes of their own to list.
+            let caller = Caller::from_request_parts(parts, state).await?;
+            params.created_by = Some(caller.subject.ok_or(AppError::Unauthorized)?);
+        }
+        Ok(params)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SearchQuotes {
+    q: String,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, FromRow)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    quote: Quote,
+    rank: f32,
+    /// HTML: the quote text, escaped, with the words that matched in `<b>`.
+    snippet: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct RandomQuote {
+    book_id: Option<uuid::Uuid>,
+    book: Option<String>,
+    /// Comma-separated tag names.
+    tag: Option<String>,
+    tag_match: Option<TagMatch>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct DailyQuote {
+    /// Defaults to the current UTC date.
+    date: Option<chrono::NaiveDate>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum SortField {
+    Id,
+    Book,
+    Quote,
+    InsertedAt,
+    UpdatedAt,
+}
+
+impl SortField {
+    fn column(self) -> &'static str {
+        match self {
+            SortField::Id => "id",
+            SortField::Book => "book",
+            SortField::Quote => "quote",
+            SortField::InsertedAt => "inserted_at",
+            SortField::UpdatedAt => "updated_at",
+        }
+    }
+
+    fn sql_type(self) -> &'static str {
+        match self {
+            SortField::Id => "uuid",
+            SortField::Book | SortField::Quote => "text",
+            SortField::InsertedAt | SortField::UpdatedAt => "timestamptz",
+        }
+    }
+
+    fn key(self, quote: &Quote) -> String {
+        match self {
+            SortField::Id => quote.id.to_string(),
+            SortField::Book => quote.book.clone(),
+            SortField::Quote => quote.quote.clone(),
+            SortField::InsertedAt => quote.inserted_at.to_rfc3339(),
+            SortField::UpdatedAt => quote.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Sort {
+    field: SortField,
+    descending: bool,
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Self {
+            field: SortField::InsertedAt,
+            descending: false,
+        }
+    }
+}
+
+impl std::str::FromStr for Sort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, order) = s.split_once(':').unwrap_or((s, "asc"));
+        let field = match field {
+            "id" => SortField::Id,
+            "book" => SortField::Book,
+            "quote" => SortField::Quote,
+            "inserted_at" => SortField::InsertedAt,
+            "updated_at" => SortField::UpdatedAt,
+            _ => return Err(format!("cannot sort by unknown field `{}`", field)),
+        };
+        let descending = match order {
+            "asc" => false,
+            "desc" => true,
+            _ => {
+                return Err(format!(
+                    "unknown sort order `{}`, expected asc or desc",
+                    order
+                ))
+            }
+        };
+        Ok(Self { field, descending })
+    }
+}
+
+impl std::fmt::Display for Sort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let order = if self.descending { "desc" } else { "asc" };
+        write!(f, "{}:{}", self.field.column(), order)
+    }
+}
+
+/// Position of the last quote on a page, ordered by `(sort key, id)`.
+struct Cursor {
+    sort: Sort,
+    id: uuid::Uuid,
+    key: String,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}|{}|{}", self.sort, self.id, self.key))
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        let raw = String::from_utf8(bytes).ok()?;
+        let mut parts = raw.splitn(3, '|');
+        Some(Self {
+            sort: parts.next()?.parse().ok()?,
+            id: uuid::Uuid::parse_str(parts.next()?).ok()?,
+            key: parts.next()?.to_string(),
+        })
+    }
+}
+
+/// Escapes the `LIKE` wildcards in `value` so it only matches literally.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Restricts `query` to the quotes carrying the comma-separated tags in `tag`.
+fn push_tag_filter(
+    query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+    tag: &str,
+    tag_match: TagMatch,
+) -> Result<(), AppError> {
+    let mut seen = std::collections::HashSet::new();
+    let tags: Vec<String> = tag
+        .split(',')
+        .map(validation::normalize_tag)
+        .filter(|tag| !tag.is_empty() && seen.insert(tag.clone()))
+        .collect();
+    if tags.is_empty() {
+        return Err(AppError::InvalidQuery {
+            param: Some("tag"),
+            message: "must name at least one tag".to_string(),
+        });
+    }
+    // Tags are unique per quote, so counting the matching ones is enough.
+    let wanted = match tag_match {
+        TagMatch::Any => 1,
+        TagMatch::All => tags.len() as i64,
+    };
+    query
+        .push(" AND (SELECT count(*) FROM quote_tags JOIN tags ON tags.id = quote_tags.tag_id")
+        .push(" WHERE quote_tags.quote_id = quotes.id AND tags.name = ANY(")
+        .push_bind(tags)
+        .push(")) >= ")
+        .push_bind(wanted);
+    Ok(())
+}
+
+/// Turns a violation of `UNIQUE (book, quote)` into a conflict naming the
+/// quote that is already stored, and a dangling `author_id` into a
+/// validation error; any other error is passed through.
+pub(crate) async fn constraint_violation(
+    executor: impl PgExecutor<'_>,
+    book: &str,
+    quote: &str,
+    err: sqlx::Error,
+) -> AppError {
+    if error::has_code(&err, error::FOREIGN_KEY_VIOLATION) {
+        return AppError::Validation(vec![FieldError {
+            field: "author_id",
+            message: "must refer to an existing author".to_string(),
+        }]);
+    }
+    if !error::has_code(&err, error::UNIQUE_VIOLATION) {
+        return err.into();
+    }
+    let existing = sqlx::query_scalar::<_, uuid::Uuid>(
+        "SELECT id FROM quotes WHERE lower(book) = lower($1) AND quote = $2 AND deleted_at IS NULL",
+    )
+    .bind(book)
+    .bind(quote)
+    .fetch_optional(executor)
+    .observe("find_conflicting_quote")
+    .await;
+    match existing {
+        Ok(Some(existing_id)) => AppError::Conflict { existing_id },
+        // The conflicting row vanished in the meantime, report the original error.
+        Ok(None) => err.into(),
+        Err(lookup_err) => lookup_err.into(),
+    }
+}
+
+/// Whether the client asked for an empty response with `Prefer: return=minimal`.
+fn prefers_minimal(headers: &http::HeaderMap) -> bool {
+    headers
+        .get_all("prefer")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|preference| preference.trim().eq_ignore_ascii_case("return=minimal"))
 }
 
 pub async fn health() -> http::StatusCode {
     http::StatusCode::OK
 }
 
+async fn insert_quote(
+    executor: impl PgExecutor<'_>,
+    payload: &CreateQuote,
+    caller: &Caller,
+) -> sqlx::Result<Quote> {
+    let sql = format!(
+        r#"
+        {UPSERT_BOOK}
+        INSERT INTO quotes
+            (id, book_id, book, author_id, quote, created_by, updated_by, inserted_at, updated_at)
+        SELECT $4, book.id, book.title, $5, $6, $7, $7, $3, $3 FROM book
+        RETURNING {QUOTE_COLUMNS}
+        "#
+    );
+    sqlx::query_as::<_, Quote>(&sql)
+        .bind(&payload.book)
+        .bind(uuid::Uuid::new_v4())
+        .bind(chrono::Utc::now())
+        .bind(uuid::Uuid::new_v4())
+        .bind(payload.author_id)
+        .bind(&payload.quote)
+        .bind(&caller.subject)
+        .fetch_one(executor)
+        .observe_one("insert_quote")
+        .await
+}
+
+/// Fetches a quote whether it is live or in the trash.
+async fn fetch_quote(executor: impl PgExecutor<'_>, id: uuid::Uuid) -> sqlx::Result<Option<Quote>> {
+    let sql = format!("SELECT {QUOTE_COLUMNS} FROM quotes WHERE id = $1");
+    sqlx::query_as::<_, Quote>(&sql)
+        .bind(id)
+        .fetch_optional(executor)
+        .observe("fetch_quote")
+        .await
+}
+
+/// Overwrites a live quote, provided the caller may change it and it still
+/// has one of the `if_match` versions.
+pub(crate) async fn replace_quote(
+    executor: impl PgExecutor<'_>,
+    id: uuid::Uuid,
+    payload: &CreateQuote,
+    caller: &Caller,
+    if_match: Option<Vec<chrono::DateTime<chrono::Utc>>>,
+) -> sqlx::Result<Option<Quote>> {
+    let sql = format!(
+        r#"
+        {UPSERT_BOOK}
+        UPDATE quotes
+        SET book_id = book.id, book = book.title, author_id = $7, quote = $4,
+            updated_by = $8, updated_at = $3
+        FROM book
+        WHERE quotes.id = $5 AND deleted_at IS NULL
+            AND ($6::timestamptz[] IS NULL OR updated_at = ANY($6))
+            AND ($9::varchar IS NULL OR created_by = $9)
+        RETURNING {QUOTE_COLUMNS}
+        "#
+    );
+    sqlx::query_as::<_, Quote>(&sql)
+        .bind(&payload.book)
+        .bind(uuid::Uuid::new_v4())
+        .bind(chrono::Utc::now())
+        .bind(&payload.quote)
+        .bind(id)
+        .bind(if_match)
+        .bind(payload.author_id)
+        .bind(&caller.subject)
+        .bind(caller.owner())
+        .fetch_optional(executor)
+        .observe("replace_quote")
+        .await
+}
+
+/// Moves a live quote to the trash, provided the caller may change it and
+/// it still has one of the `if_match` versions.
+async fn trash_quote(
+    executor: impl PgExecutor<'_>,
+    id: uuid::Uuid,
+    caller: &Caller,
+    if_match: Option<Vec<chrono::DateTime<chrono::Utc>>>,
+) -> sqlx::Result<Option<Quote>> {
+    let sql = format!(
+        r#"
+        UPDATE quotes
+        SET deleted_at = $2, updated_by = $4
+        WHERE id = $1 AND deleted_at IS NULL
+            AND ($3::timestamptz[] IS NULL OR updated_at = ANY($3))
+            AND ($5::varchar IS NULL OR created_by = $5)
+        RETURNING {QUOTE_COLUMNS}
+        "#
+    );
+    sqlx::query_as::<_, Quote>(&sql)
+        .bind(id)
+        .bind(chrono::Utc::now())
+        .bind(if_match)
+        .bind(&caller.subject)
+        .bind(caller.owner())
+        .fetch_optional(executor)
+        .observe("trash_quote")
+        .await
+}
+
 pub async fn create_quote(
     extract::State(pool): extract::State<PgPool>,
-    axum::Json(payload): axum::Json<CreateQuote>,
-) -> Result<(http::StatusCode, axum::Json<Quote>), http::StatusCode> {
-    let quote = Quote::new(payload.book, payload.quote);
-    let res = sqlx::query(
+    caller: Caller,
+    ValidJson(payload): ValidJson<CreateQuote>,
+) -> Result<(http::StatusCode, axum::Json<Quote>), AppError> {
+    let mut conn = metrics::acquire(&pool).await?;
+    match insert_quote(&mut *conn, &payload, &caller).await {
+        Ok(quote) => Ok((http::StatusCode::CREATED, axum::Json(quote))),
+        Err(err) => Err(constraint_violation(&mut *conn, &payload.book, &payload.quote, err).await),
+    }
+}
+
+pub async fn read_quotes(
+    extract::State(pool): extract::State<PgPool>,
+    params: ListQuotes,
+) -> Result<axum::Json<Page<Quote>>, AppError> {
+    let mut conn = metrics::acquire(&pool).await?;
+    list_quotes(&mut conn, params, false).await.map(axum::Json)
+}
+
+pub async fn read_trash(
+    extract::State(pool): extract::State<PgPool>,
+    params: ListQuotes,
+) -> Result<axum::Json<Page<Quote>>, AppError> {
+    let mut conn = metrics::acquire(&pool).await?;
+    list_quotes(&mut conn, params, true).await.map(axum::Json)
+}
+
+/// Lists one page of either the live quotes or the ones in the trash.
+pub(crate) async fn list_quotes(
+    conn: &mut PgConnection,
+    params: ListQuotes,
+    trashed: bool,
+) -> Result<Page<Quote>, AppError> {
+    let limit = pagination::page_size(params.limit);
+    let sort = match params.sort.as_deref() {
+        Some(raw) => raw
+            .parse::<Sort>()
+            .map_err(|message| AppError::InvalidQuery {
+                param: Some("sort"),
+                message,
+            })?,
+        None => Sort::default(),
+    };
+    let cursor = match params.cursor.as_deref() {
+        Some(raw) => match Cursor::decode(raw) {
+            Some(cursor) if cursor.sort == sort => Some(cursor),
+            _ => {
+                return Err(AppError::InvalidQuery {
+                    param: Some("cursor"),
+                    message: "cursor is malformed or was issued for a different sort".to_string(),
+                })
+            }
+        },
+        None => None,
+    };
+
+    let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new(format!(
+        "SELECT {QUOTE_COLUMNS} FROM quotes WHERE deleted_at IS {} NULL",
+        if trashed { "NOT" } else { "" }
+    ));
+    if let Some(book_id) = params.book_id {
+        query.push(" AND book_id = ").push_bind(book_id);
+    }
+    if let Some(author_id) = params.author_id {
+        // Quotes credited to nobody in particular belong to the author of their book.
+        query
+            .push(" AND (author_id = ")
+            .push_bind(author_id)
+            .push(" OR (author_id IS NULL AND book_id IN (SELECT id FROM books WHERE author_id = ")
+            .push_bind(author_id)
+            .push(")))");
+    }
+    if let Some(book) = params.book {
+        query.push(" AND book = ").push_bind(book);
+    }
+    if let Some(quote) = params.quote {
+        query
+            .push(" AND quote ILIKE '%' || ")
+            .push_bind(escape_like(&quote))
+            .push(" || '%'");
+    }
+    if let Some(tag) = params.tag {
+        push_tag_filter(&mut query, &tag, params.tag_match.unwrap_or_default())?;
+    }
+    if let Some(created_by) = params.created_by {
+        query.push(" AND created_by = ").push_bind(created_by);
+    }
+    if let Some(after) = params.inserted_after {
+        query.push(" AND inserted_at >= ").push_bind(after);
+    }
+    if let Some(before) = params.inserted_before {
+        query.push(" AND inserted_at < ").push_bind(before);
+    }
+    if let Some(after) = params.updated_after {
+        query.push(" AND updated_at >= ").push_bind(after);
+    }
+    if let Some(before) = params.updated_before {
+        query.push(" AND updated_at < ").push_bind(before);
+    }
+    let column = sort.field.column();
+    let (comparison, order) = if sort.descending {
+        ("<", "DESC")
+    } else {
+        (">", "ASC")
+    };
+    if let Some(cursor) = cursor {
+        query
+            .push(format_args!(" AND ({}, id) {} (", column, comparison))
+            .push_bind(cursor.key)
+            .push(format_args!("::{}, ", sort.field.sql_type()))
+            .push_bind(cursor.id)
+            .push(")");
+    }
+    query
+        .push(format_args!(
+            " ORDER BY {} {}, id {} LIMIT ",
+            column, order, order
+        ))
+        .push_bind(limit + 1);
+
+    let quotes = query
+        .build_query_as::<Quote>()
+        .fetch_all(conn)
+        .observe("list_quotes")
+        .await?;
+    Ok(Page::from_rows(quotes, limit, |quote| {
+        Cursor {
+            sort,
+            id: quote.id,
+            key: sort.field.key(quote),
+        }
+        .encode()
+    }))
+}
+
+pub async fn search_quotes(
+    extract::State(pool): extract::State<PgPool>,
+    ValidQuery(params): ValidQuery<SearchQuotes>,
+) -> Result<axum::Json<Vec<SearchResult>>, AppError> {
+    if params.q.trim().is_empty() {
+        return Err(AppError::InvalidQuery {
+            param: Some("q"),
+            message: "search query must not be empty".to_string(),
+        });
+    }
+    let limit = pagination::page_size(params.limit);
+    let mut conn = metrics::acquire(&pool).await?;
+    let sql = format!(
+        r#"
+        SELECT {QUOTE_COLUMNS},
+            ts_rank(search, query) AS rank,
+            ts_headline('english', {ESCAPED_QUOTE}, query) AS snippet
+        FROM quotes, websearch_to_tsquery('english', $1) AS query
+        WHERE search @@ query AND deleted_at IS NULL
+        ORDER BY rank DESC, id
+        LIMIT $2
+        "#
+    );
+    let results = sqlx::query_as::<_, SearchResult>(&sql)
+        .bind(&params.q)
+        .bind(limit)
+        .fetch_all(&mut *conn)
+        .observe("search_quotes")
+        .await?;
+    Ok(axum::Json(results))
+}
+
+/// Random fractions in `[0, 1)` for `pick_quote`: one per number it tries
+/// and one more for when none of them passes.
+fn draws(rng: &mut impl rand::Rng) -> Vec<f64> {
+    (0..=DRAWS).map(|_| rng.gen()).collect()
+}
+
+/// Picks a live quote that passes `filter`, each as likely as the others
+/// when `draws` are uniform. Each draw but the last names the live quote
+/// numbered that far along `draw_position`, an index lookup, and the first
+/// of them to pass wins. When none does, the last draw is taken that far
+/// along the quotes that pass in id order, which means counting them.
+async fn pick_quote(
+    conn: &mut PgConnection,
+    draws: &[f64],
+    filter: impl Fn(&mut sqlx::QueryBuilder<'_, sqlx::Postgres>) -> Result<(), AppError>,
+) -> Result<Option<Quote>, AppError> {
+    let (fallback, draws) = draws.split_last().expect("a pick takes at least one draw");
+    let mut query =
+        sqlx::QueryBuilder::<sqlx::Postgres>::new(format!("SELECT {QUOTE_COLUMNS} FROM unnest("));
+    query
+        .push_bind(draws.to_vec())
+        .push("::float8[]) WITH ORDINALITY AS draw (fraction, n)")
+        .push(" CROSS JOIN live_quotes JOIN quotes")
+        .push(" ON draw_position = floor(draw.fraction * live_quotes.count)::bigint")
+        .push(" WHERE deleted_at IS NULL");
+    filter(&mut query)?;
+    query.push(" ORDER BY draw.n LIMIT 1");
+    let quote = query
+        .build_query_as::<Quote>()
+        .fetch_optional(&mut *conn)
+        .observe("draw_quote")
+        .await?;
+    if quote.is_some() {
+        return Ok(quote);
+    }
+    let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new(format!(
+        "SELECT {QUOTE_COLUMNS} FROM quotes WHERE deleted_at IS NULL"
+    ));
+    filter(&mut query)?;
+    query
+        .push(" ORDER BY id OFFSET floor(")
+        .push_bind(*fallback)
+        .push(" * (SELECT count(*) FROM quotes WHERE deleted_at IS NULL");
+    filter(&mut query)?;
+    query.push("))::bigint LIMIT 1");
+    let quote = query
+        .build_query_as::<Quote>()
+        .fetch_optional(conn)
+        .observe("pick_quote")
+        .await?;
+    Ok(quote)
+}
+
+pub async fn random_quote(
+    extract::State(pool): extract::State<PgPool>,
+    ValidQuery(params): ValidQuery<RandomQuote>,
+) -> Result<axum::Json<Quote>, AppError> {
+    let draws = draws(&mut rand::thread_rng());
+    let mut conn = metrics::acquire(&pool).await?;
+    let quote = pick_quote(&mut conn, &draws, |query| {
+        if let Some(book_id) = params.book_id {
+            query.push(" AND book_id = ").push_bind(book_id);
+        }
+        if let Some(book) = &params.book {
+            query.push(" AND book = ").push_bind(book.clone());
+        }
+        if let Some(tag) = &params.tag {
+            push_tag_filter(query, tag, params.tag_match.unwrap_or_default())?;
+        }
+        Ok(())
+    })
+    .await?;
+    quote.map(axum::Json).ok_or(AppError::NotFound)
+}
+
+/// The live quote kept as the quote of `date`, if any.
+async fn fetch_daily_quote(
+    executor: impl PgExecutor<'_>,
+    date: chrono::NaiveDate,
+) -> sqlx::Result<Option<Quote>> {
+    let sql = format!(
+        r#"
+        SELECT {QUOTE_COLUMNS} FROM daily_quotes
+        JOIN quotes ON quotes.id = daily_quotes.quote_id
+        WHERE day = $1 AND deleted_at IS NULL
+        "#
+    );
+    sqlx::query_as::<_, Quote>(&sql)
+        .bind(date)
+        .fetch_optional(executor)
+        .observe("fetch_daily_quote")
+        .await
+}
+
+/// The quote of a day is picked from the quotes added before it began, the
+/// first time the day is asked for, and kept in `daily_quotes`. It stays
+/// the same for everyone however other quotes come and go, and is only
+/// picked again once it is deleted itself. Days yet to come are not kept.
+pub async fn daily_quote(
+    extract::State(pool): extract::State<PgPool>,
+    ValidQuery(params): ValidQuery<DailyQuote>,
+) -> Result<axum::Json<Quote>, AppError> {
+    let today = chrono::Utc::now().date_naive();
+    let date = params.date.unwrap_or(today);
+    let mut conn = metrics::acquire(&pool).await?;
+    if let Some(quote) = fetch_daily_quote(&mut *conn, date).await? {
+        return Ok(axum::Json(quote));
+    }
+    // Seeding the draw with the date makes instances that pick at once
+    // likely to agree.
+    let seed = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, date.to_string().as_bytes());
+    let draws = draws(&mut rand::rngs::StdRng::seed_from_u64(seed.as_u64_pair().0));
+    let start = date.and_time(chrono::NaiveTime::MIN).and_utc();
+    let quote = pick_quote(&mut conn, &draws, |query| {
+        query.push(" AND inserted_at < ").push_bind(start);
+        Ok(())
+    })
+    .await?
+    .ok_or(AppError::NotFound)?;
+    if date > today {
+        return Ok(axum::Json(quote));
+    }
+    sqlx::query(
         r#"
-        INSERT INTO quotes (id, book, quote, inserted_at, updated_at)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO daily_quotes (day, quote_id) VALUES ($1, $2)
+        ON CONFLICT (day) DO UPDATE SET quote_id = EXCLUDED.quote_id
+        WHERE NOT EXISTS (
+            SELECT 1 FROM quotes WHERE id = daily_quotes.quote_id AND deleted_at IS NULL
+        )
         "#,
     )
+    .bind(date)
     .bind(quote.id)
-    .bind(&quote.book)
-    .bind(&quote.quote)
-    .bind(quote.inserted_at)
-    .bind(quote.updated_at)
-    .execute(&pool)
+    .execute(&mut *conn)
+    .observe("keep_daily_quote")
+    .await?;
+    // Another instance may have kept its pick first.
+    let kept = fetch_daily_quote(&mut *conn, date).await?;
+    Ok(axum::Json(kept.unwrap_or(quote)))
+}
+
+pub async fn read_quote(
+    extract::State(pool): extract::State<PgPool>,
+    ValidPath(id): ValidPath<uuid::Uuid>,
+    headers: http::HeaderMap,
+) -> Result<Response, AppError> {
+    let mut conn = metrics::acquire(&pool).await?;
+    let quote = fetch_quote(&mut *conn, id)
+        .await?
+        .filter(|quote| quote.deleted_at.is_none())
+        .ok_or(AppError::NotFound)?;
+    if etag::if_none_match(&headers, quote.updated_at) {
+        let etag = etag::etag(quote.updated_at);
+        return Ok((http::StatusCode::NOT_MODIFIED, [(http::header::ETAG, etag)]).into_response());
+    }
+    Ok(Tagged(quote).into_response())
+}
+
+/// Explains why a conditional write to a quote, live or in the trash as
+/// `trashed` says, matched no row: the quote does not exist, the caller may
+/// not change it, or it no longer has the version named in `If-Match`.
+pub(crate) async fn missing_or_modified(
+    executor: impl PgExecutor<'_>,
+    id: uuid::Uuid,
+    caller: &Caller,
+    trashed: bool,
+) -> AppError {
+    let created_by = sqlx::query_scalar::<_, Option<String>>(
+        "SELECT created_by FROM quotes WHERE id = $1 AND (deleted_at IS NOT NULL) = $2",
+    )
+    .bind(id)
+    .bind(trashed)
+    .fetch_optional(executor)
+    .observe("find_quote_owner")
     .await;
+    match created_by {
+        Ok(None) => AppError::NotFound,
+        Ok(Some(created_by)) => match caller.owner() {
+            Some(owner) if created_by.as_deref() != Some(owner) => AppError::Forbidden(
+                "only the caller that added the quote or an admin may change it".to_string(),
+            ),
+            _ => AppError::PreconditionFailed,
+        },
+        Err(err) => err.into(),
+    }
+}
 
+pub async fn update_quote(
+    extract::State(pool): extract::State<PgPool>,
+    ValidPath(id): ValidPath<uuid::Uuid>,
+    caller: Caller,
+    headers: http::HeaderMap,
+    ValidJson(payload): ValidJson<CreateQuote>,
+) -> Result<Tagged, AppError> {
+    let mut conn = metrics::acquire(&pool).await?;
+    let res = replace_quote(&mut *conn, id, &payload, &caller, etag::if_match(&headers)).await;
     match res {
-        Ok(_) => Ok((http::StatusCode::CREATED, axum::Json(quote))),
-        Err(_) => Err(http::StatusCode::INTERNAL_SERVER_ERROR),
+        Ok(Some(quote)) => Ok(Tagged(quote)),
+        Ok(None) => Err(missing_or_modified(&mut *conn, id, &caller, false).await),
+        Err(err) => Err(constraint_violation(&mut *conn, &payload.book, &payload.quote, err).await),
     }
 }
 
-pub async fn read_quotes(
+pub async fn patch_quote(
     extract::State(pool): extract::State<PgPool>,
-) -> Result<axum::Json<Vec<Quote>>, http::StatusCode> {
-    let res = sqlx::query_as::<_, Quote>("SELECT * FROM quotes")
-        .fetch_all(&pool)
+    ValidPath(id): ValidPath<uuid::Uuid>,
+    caller: Caller,
+    headers: http::HeaderMap,
+    ValidJson(payload): ValidJson<PatchQuote>,
+) -> Result<Tagged, AppError> {
+    let sql = format!(
+        r#"
+        {UPSERT_BOOK}
+        UPDATE quotes
+        SET book_id = COALESCE((SELECT id FROM book), book_id),
+            book = COALESCE((SELECT title FROM book), book),
+            author_id = CASE WHEN $10 THEN $7 ELSE author_id END,
+            quote = COALESCE($4, quote),
+            updated_by = $8,
+            updated_at = $3
+        WHERE id = $5 AND deleted_at IS NULL
+            AND ($6::timestamptz[] IS NULL OR updated_at = ANY($6))
+            AND ($9::varchar IS NULL OR created_by = $9)
+        RETURNING {QUOTE_COLUMNS}
+        "#
+    );
+    let mut conn = metrics::acquire(&pool).await?;
+    let res = sqlx::query_as::<_, Quote>(&sql)
+        .bind(&payload.book)
+        .bind(uuid::Uuid::new_v4())
+        .bind(chrono::Utc::now())
+        .bind(&payload.quote)
+        .bind(id)
+        .bind(etag::if_match(&headers))
+        .bind(payload.author_id.flatten())
+        .bind(&caller.subject)
+        .bind(caller.owner())
+        .bind(payload.author_id.is_some())
+        .fetch_optional(&mut *conn)
+        .observe("patch_quote")
         .await;
     match res {
-        Ok(quotes) => Ok(axum::Json(quotes)),
-        Err(_) => Err(http::StatusCode::INTERNAL_SERVER_ERROR),
+        Ok(Some(quote)) => Ok(Tagged(quote)),
+        Ok(None) => Err(missing_or_modified(&mut *conn, id, &caller, false).await),
+        Err(err) => {
+            // Work out which (book, quote) pair the update collided on.
+            let current = fetch_quote(&mut *conn, id)
+                .await?
+                .filter(|quote| quote.deleted_at.is_none())
+                .ok_or(AppError::NotFound)?;
+            let book = payload.book.as_deref().unwrap_or(&current.book);
+            let quote = payload.quote.as_deref().unwrap_or(&current.quote);
+            Err(constraint_violation(&mut *conn, book, quote, err).await)
+        }
     }
 }
 
-pub async fn update_quote(
+pub async fn delete_quote(
+    extract::State(pool): extract::State<PgPool>,
+    ValidPath(id): ValidPath<uuid::Uuid>,
+    caller: Caller,
+    headers: http::HeaderMap,
+) -> Result<Response, AppError> {
+    let mut conn = metrics::acquire(&pool).await?;
+    let quote = trash_quote(&mut *conn, id, &caller, etag::if_match(&headers)).await?;
+    let Some(quote) = quote else {
+        return Err(missing_or_modified(&mut *conn, id, &caller, false).await);
+    };
+    if prefers_minimal(&headers) {
+        Ok(http::StatusCode::NO_CONTENT.into_response())
+    } else {
+        Ok(axum::Json(quote).into_response())
+    }
+}
+
+pub async fn restore_quote(
+    extract::State(pool): extract::State<PgPool>,
+    ValidPath(id): ValidPath<uuid::Uuid>,
+    caller: Caller,
+) -> Result<Tagged, AppError> {
+    let sql = format!(
+        r#"
+        UPDATE quotes
+        SET deleted_at = NULL, updated_by = $2
+        WHERE id = $1 AND deleted_at IS NOT NULL
+            AND ($3::varchar IS NULL OR created_by = $3)
+        RETURNING {QUOTE_COLUMNS}
+        "#
+    );
+    let mut conn = metrics::acquire(&pool).await?;
+    let res = sqlx::query_as::<_, Quote>(&sql)
+        .bind(id)
+        .bind(&caller.subject)
+        .bind(caller.owner())
+        .fetch_optional(&mut *conn)
+        .observe("restore_quote")
+        .await;
+    match res {
+        Ok(Some(quote)) => Ok(Tagged(quote)),
+        Ok(None) => Err(missing_or_modified(&mut *conn, id, &caller, true).await),
+        Err(err) => {
+            // The same quote was added again while this one was in the trash.
+            let trashed = fetch_quote(&mut *conn, id)
+                .await?
+                .ok_or(AppError::NotFound)?;
+            Err(constraint_violation(&mut *conn, &trashed.book, &trashed.quote, err).await)
+        }
+    }
+}
+
+/// Replaces the whole set of tags of a live quote, creating the tags that
+/// are new. The quote counts as modified, so its `ETag` changes.
+pub async fn replace_tags(
     extract::State(pool): extract::State<PgPool>,
-    extract::Path(id): extract::Path<uuid::Uuid>,
-    axum::Json(payload): axum::Json<CreateQuote>,
-) -> http::StatusCode {
+    ValidPath(id): ValidPath<uuid::Uuid>,
+    caller: Caller,
+    headers: http::HeaderMap,
+    ValidJson(payload): ValidJson<ReplaceTags>,
+) -> Result<Tagged, AppError> {
     let now = chrono::Utc::now();
-    let res = sqlx::query(
+    let mut conn = metrics::acquire(&pool).await?;
+    let mut tx = conn.begin().await?;
+    let touched = sqlx::query(
         r#"
         UPDATE quotes
-        SET book = $1, quote = $2, updated_at = $3
-        WHERE id = $4
+        SET updated_by = $4, updated_at = $2
+        WHERE id = $1 AND deleted_at IS NULL
+            AND ($3::timestamptz[] IS NULL OR updated_at = ANY($3))
+            AND ($5::varchar IS NULL OR created_by = $5)
         "#,
     )
-    .bind(&payload.book)
-    .bind(&payload.quote)
-    .bind(now)
     .bind(id)
-    .execute(&pool)
-    .await
-    .map(|res| match res.rows_affected() {
-        0 => http::StatusCode::NOT_FOUND,
-        _ => http::StatusCode::OK,
-    });
-    match res {
-        Ok(status) => status,
-        Err(_) => http::StatusCode::INTERNAL_SERVER_ERROR,
+    .bind(now)
+    .bind(etag::if_match(&headers))
+    .bind(&caller.subject)
+    .bind(caller.owner())
+    .execute(&mut *tx)
+    .observe("touch_quote")
+    .await?;
+    if touched.rows_affected() == 0 {
+        return Err(missing_or_modified(&mut *tx, id, &caller, false).await);
     }
+    sqlx::query(
+        r#"
+        INSERT INTO tags (id, name, inserted_at)
+        SELECT gen_random_uuid(), name, $2 FROM unnest($1::varchar[]) AS name
+        ON CONFLICT (name) DO NOTHING
+        "#,
+    )
+    .bind(&payload.tags)
+    .bind(now)
+    .execute(&mut *tx)
+    .observe("upsert_tags")
+    .await?;
+    sqlx::query("DELETE FROM quote_tags WHERE quote_id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .observe("clear_quote_tags")
+        .await?;
+    sqlx::query(
+        "INSERT INTO quote_tags (quote_id, tag_id) SELECT $1, id FROM tags WHERE name = ANY($2)",
+    )
+    .bind(id)
+    .bind(&payload.tags)
+    .execute(&mut *tx)
+    .observe("tag_quote")
+    .await?;
+    let quote = fetch_quote(&mut *tx, id).await?.ok_or(AppError::NotFound)?;
+    tx.commit().await?;
+    Ok(Tagged(quote))
 }
 
-pub async fn delete_quote(
+pub async fn bulk_quotes(
     extract::State(pool): extract::State<PgPool>,
-    extract::Path(id): extract::Path<uuid::Uuid>,
-) -> http::StatusCode {
-    let res = sqlx::query("DELETE FROM quotes WHERE id = $1")
-        .bind(id)
-        .execute(&pool)
-        .await
-        .map(|res| match res.rows_affected() {
-            0 => http::StatusCode::NOT_FOUND,
-            _ => http::StatusCode::OK,
-        });
-    match res {
-        Ok(status) => status,
-        Err(_) => http::StatusCode::INTERNAL_SERVER_ERROR,
+    extract::State(limits): extract::State<Limits>,
+    caller: Caller,
+    ValidJson(payload): ValidJson<BulkRequest>,
+) -> Result<axum::Json<BulkResponse>, AppError> {
+    let total = payload.operations.len();
+    let mut conn = metrics::acquire(&pool).await?;
+    let mut tx = conn.begin().await?;
+    let mut results = Vec::with_capacity(total);
+    let mut failed = false;
+    for mut operation in payload.operations {
+        let res = match operation.validate(&limits) {
+            Ok(()) => apply(&mut tx, operation, &caller).await,
+            Err(errors) => Err(AppError::Validation(errors)),
+        };
+        failed |= res.is_err();
+        results.push(res);
+        if failed && payload.mode == BulkMode::AllOrNothing {
+            break;
+        }
     }
+
+    let committed = !failed || payload.mode == BulkMode::BestEffort;
+    if committed {
+        tx.commit().await?;
+    } else {
+        tx.rollback().await?;
+        // Nothing was written, so only the failure itself is worth reporting.
+        for res in results.iter_mut() {
+            if res.is_ok() {
+                *res = Err(AppError::RolledBack);
+            }
+        }
+        results.resize_with(total, || Err(AppError::RolledBack));
+    }
+    Ok(axum::Json(BulkResponse {
+        committed,
+        results: results.into_iter().map(BulkResult::from).collect(),
+    }))
 }
 
-#[sqlx::test(fixtures("quotes"))]
+/// Runs one bulk operation in a savepoint, so that a failed operation leaves
+/// the surrounding transaction usable.
+async fn apply(
+    conn: &mut PgConnection,
+    operation: BulkOperation,
+    caller: &Caller,
+) -> Result<(http::StatusCode, Quote), AppError> {
+    let mut savepoint = conn.begin().await?;
+    let res = match &operation {
+        BulkOperation::Create(payload) => insert_quote(&mut *savepoint, payload, caller)
+            .await
+            .map(|quote| Some((http::StatusCode::CREATED, quote))),
+        BulkOperation::Update { id, quote } => {
+            replace_quote(&mut *savepoint, *id, quote, caller, None)
+                .await
+                .map(|quote| quote.map(|quote| (http::StatusCode::OK, quote)))
+        }
+        BulkOperation::Delete { id } => trash_quote(&mut *savepoint, *id, caller, None)
+            .await
+            .map(|quote| quote.map(|quote| (http::StatusCode::OK, quote))),
+    };
+    match res {
+        Ok(Some(done)) => {
+            savepoint.commit().await?;
+            Ok(done)
+        }
+        Ok(None) => {
+            savepoint.rollback().await?;
+            match operation {
+                BulkOperation::Update { id, .. } | BulkOperation::Delete { id } => {
+                    Err(missing_or_modified(&mut *conn, id, caller, false).await)
+                }
+                BulkOperation::Create(_) => Err(AppError::NotFound),
+            }
+        }
+        Err(err) => {
+            savepoint.rollback().await?;
+            match operation {
+                BulkOperation::Create(payload) | BulkOperation::Update { quote: payload, .. } => {
+                    Err(constraint_violation(&mut *conn, &payload.book, &payload.quote, err).await)
+                }
+                BulkOperation::Delete { .. } => Err(err.into()),
+            }
+        }
+    }
+}
+
+#[sqlx::test(fixtures("quotes"))]
 async fn test_create_quote(pool: PgPool) -> sqlx::Result<()> {
-    let quote = Quote::new("book".to_string(), "quote".to_string());
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
     let res = create_quote(
         extract::State(pool),
-        axum::Json(CreateQuote {
-            book: quote.book.clone(),
-            quote: quote.quote.clone(),
+        admin.clone(),
+        ValidJson(CreateQuote {
+            book: "book".to_string(),
+            quote: "quote".to_string(),
+            author_id: None,
         }),
     )
     .await;
@@ -133,53 +1258,931 @@ async fn test_create_quote(pool: PgPool) -> sqlx::Result<()> {
     Ok(())
 }
 
+#[sqlx::test(fixtures("quotes"))]
+async fn test_create_quote_for_existing_book(pool: PgPool) -> sqlx::Result<()> {
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
+    let res = create_quote(
+        extract::State(pool),
+        admin.clone(),
+        ValidJson(CreateQuote {
+            book: "the hobbit".to_string(),
+            quote: "Where there's life there's hope.".to_string(),
+            author_id: None,
+        }),
+    )
+    .await;
+    // the quote is filed under the book that is already known
+    let (_, axum::Json(quote)) = res.unwrap();
+    assert_eq!(
+        quote.book_id,
+        uuid::Uuid::parse_str("b1eebc99-9c0b-4ef8-bb6d-6bb9bd380a22").unwrap()
+    );
+    assert_eq!(quote.book, "The Hobbit");
+    Ok(())
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_create_duplicate_quote(pool: PgPool) -> sqlx::Result<()> {
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
+    let res = create_quote(
+        extract::State(pool),
+        admin.clone(),
+        ValidJson(CreateQuote {
+            book: "The Hobbit".to_string(),
+            quote: "In a hole in the ground there lived a hobbit.".to_string(),
+            author_id: None,
+        }),
+    )
+    .await;
+    match res {
+        Err(AppError::Conflict { existing_id }) => assert_eq!(
+            existing_id,
+            uuid::Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap()
+        ),
+        _ => panic!("expected a conflict"),
+    }
+    Ok(())
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_create_quote_for_unknown_author(pool: PgPool) -> sqlx::Result<()> {
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
+    let res = create_quote(
+        extract::State(pool),
+        admin.clone(),
+        ValidJson(CreateQuote {
+            book: "The Hobbit".to_string(),
+            quote: "Not all those who wander are lost.".to_string(),
+            author_id: Some(uuid::Uuid::new_v4()),
+        }),
+    )
+    .await;
+    match res {
+        Err(AppError::Validation(errors)) => assert_eq!(errors[0].field, "author_id"),
+        _ => panic!("expected a validation error"),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_validate_create_quote() {
+    use axum::extract::FromRequest;
+
+    let limits = Limits {
+        max_book_length: 8,
+        max_author_length: 8,
+        max_quote_length: 16,
+    };
+    let request = |body: &str| {
+        http::Request::builder()
+            .method(http::Method::POST)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(axum::body::Body::from(body.to_string()))
+            .unwrap()
+    };
+    // fields are trimmed and NFC-normalized before they are checked
+    let res = ValidJson::<CreateQuote>::from_request(
+        request(r#"{"book": "  Cafe\u0301 ", "quote": "quote"}"#),
+        &limits,
+    )
+    .await;
+    let ValidJson(payload) = res.unwrap();
+    assert_eq!(payload.book, "Caf\u{e9}");
+    let res = ValidJson::<CreateQuote>::from_request(
+        request(r#"{"book": "   ", "quote": "a quote that is far too long"}"#),
+        &limits,
+    )
+    .await;
+    match res {
+        Err(AppError::Validation(errors)) => {
+            let fields: Vec<_> = errors.iter().map(|err| err.field).collect();
+            assert_eq!(fields, ["book", "quote"]);
+        }
+        _ => panic!("expected a validation error"),
+    }
+    // malformed JSON is reported in the same format
+    let res = ValidJson::<CreateQuote>::from_request(request(r#"{"book": "book"}"#), &limits).await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::UNPROCESSABLE_ENTITY)
+    );
+}
+
 #[sqlx::test(fixtures("quotes"))]
 async fn test_read_quotes(pool: PgPool) -> sqlx::Result<()> {
-    let res = read_quotes(extract::State(pool)).await;
+    let res = read_quotes(extract::State(pool), ListQuotes::default()).await;
     assert!(res.is_ok());
     let quotes = res.unwrap();
-    assert_eq!(quotes.0.len(), 1);
+    assert_eq!(quotes.0.data.len(), 1);
+    assert!(quotes.0.next_cursor.is_none());
     // The result contains one quote with id a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11
     assert_eq!(
-        quotes.0[0].id,
+        quotes.0.data[0].id,
+        uuid::Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap()
+    );
+    Ok(())
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_read_quotes_paginated(pool: PgPool) -> sqlx::Result<()> {
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
+    for i in 0..4 {
+        let res = create_quote(
+            extract::State(pool.clone()),
+            admin.clone(),
+            ValidJson(CreateQuote {
+                book: "book".to_string(),
+                quote: format!("quote {}", i),
+                author_id: None,
+            }),
+        )
+        .await;
+        assert!(res.is_ok());
+    }
+    let mut seen = Vec::new();
+    let mut cursor = None;
+    loop {
+        let res = read_quotes(
+            extract::State(pool.clone()),
+            ListQuotes {
+                limit: Some(2),
+                cursor,
+                ..Default::default()
+            },
+        )
+        .await;
+        let page = res.unwrap().0;
+        assert!(page.data.len() <= 2);
+        seen.extend(page.data.into_iter().map(|quote| quote.id));
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    // every quote is returned exactly once across the pages
+    assert_eq!(seen.len(), 5);
+    seen.sort();
+    seen.dedup();
+    assert_eq!(seen.len(), 5);
+    // a cursor that was not issued by the server is rejected
+    let res = read_quotes(
+        extract::State(pool),
+        ListQuotes {
+            cursor: Some("not a cursor".to_string()),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::BAD_REQUEST)
+    );
+    Ok(())
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_read_quotes_filtered_and_sorted(pool: PgPool) -> sqlx::Result<()> {
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
+    for (book, quote) in [("Dune", "Fear is the mind-killer."), ("Dune", "100% spice")] {
+        let res = create_quote(
+            extract::State(pool.clone()),
+            admin.clone(),
+            ValidJson(CreateQuote {
+                book: book.to_string(),
+                quote: quote.to_string(),
+                author_id: None,
+            }),
+        )
+        .await;
+        assert!(res.is_ok());
+    }
+    let res = read_quotes(
+        extract::State(pool.clone()),
+        ListQuotes {
+            book: Some("Dune".to_string()),
+            sort: Some("quote:desc".to_string()),
+            ..Default::default()
+        },
+    )
+    .await;
+    let quotes = res.unwrap().0.data;
+    assert_eq!(quotes.len(), 2);
+    assert_eq!(quotes[0].quote, "Fear is the mind-killer.");
+    // wildcards in the substring filter are matched literally
+    let res = read_quotes(
+        extract::State(pool.clone()),
+        ListQuotes {
+            quote: Some("0%".to_string()),
+            ..Default::default()
+        },
+    )
+    .await;
+    let quotes = res.unwrap().0.data;
+    assert_eq!(quotes.len(), 1);
+    assert_eq!(quotes[0].quote, "100% spice");
+    let res = read_quotes(
+        extract::State(pool.clone()),
+        ListQuotes {
+            inserted_before: Some("2021-01-01T00:00:00Z".parse().unwrap()),
+            ..Default::default()
+        },
+    )
+    .await;
+    let quotes = res.unwrap().0.data;
+    assert_eq!(quotes.len(), 1);
+    assert_eq!(quotes[0].book, "The Hobbit");
+    // only the columns of a quote can be sorted on
+    let res = read_quotes(
+        extract::State(pool),
+        ListQuotes {
+            sort: Some("author:asc".to_string()),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::BAD_REQUEST)
+    );
+    Ok(())
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_search_quotes(pool: PgPool) -> sqlx::Result<()> {
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
+    let res = create_quote(
+        extract::State(pool.clone()),
+        admin.clone(),
+        ValidJson(CreateQuote {
+            book: "The Fellowship of the Ring".to_string(),
+            quote: "Not all those who wander are lost.".to_string(),
+            author_id: None,
+        }),
+    )
+    .await;
+    assert!(res.is_ok());
+    let res = search_quotes(
+        extract::State(pool.clone()),
+        ValidQuery(SearchQuotes {
+            q: "\"hole in the ground\"".to_string(),
+            limit: None,
+        }),
+    )
+    .await;
+    let results = res.unwrap().0;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].quote.book, "The Hobbit");
+    assert!(results[0].rank > 0.0);
+    assert!(results[0].snippet.contains("<b>hole</b>"));
+    // quotes are escaped before their matches are highlighted
+    let res = create_quote(
+        extract::State(pool.clone()),
+        admin.clone(),
+        ValidJson(CreateQuote {
+            book: "The Hobbit".to_string(),
+            quote: "<img src=x onerror=alert(1)> Riddles & dark holes".to_string(),
+            author_id: None,
+        }),
+    )
+    .await;
+    assert!(res.is_ok());
+    let res = search_quotes(
+        extract::State(pool.clone()),
+        ValidQuery(SearchQuotes {
+            q: "riddles".to_string(),
+            limit: None,
+        }),
+    )
+    .await;
+    let results = res.unwrap().0;
+    assert_eq!(
+        results[0].snippet,
+        "&lt;img src=x onerror=alert(1)&gt; <b>Riddles</b> &amp; dark holes"
+    );
+    // the book title is searched as well as the quote
+    let res = search_quotes(
+        extract::State(pool.clone()),
+        ValidQuery(SearchQuotes {
+            q: "fellowship".to_string(),
+            limit: None,
+        }),
+    )
+    .await;
+    let results = res.unwrap().0;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].quote.book, "The Fellowship of the Ring");
+    let res = search_quotes(
+        extract::State(pool),
+        ValidQuery(SearchQuotes {
+            q: "  ".to_string(),
+            limit: None,
+        }),
+    )
+    .await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::BAD_REQUEST)
+    );
+    Ok(())
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_random_quote(pool: PgPool) -> sqlx::Result<()> {
+    let res = random_quote(
+        extract::State(pool.clone()),
+        ValidQuery(RandomQuote {
+            book: Some("The Hobbit".to_string()),
+            ..Default::default()
+        }),
+    )
+    .await;
+    assert_eq!(
+        res.unwrap().0.id,
         uuid::Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap()
     );
+    // each draw names a live quote by its number, here the fixture's 0 and
+    // the Dune quotes' 1 and 2, and the first that passes the filter wins
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
+    let mut dune = Vec::new();
+    for quote in ["Fear is the mind-killer.", "The spice must flow."] {
+        let res = create_quote(
+            extract::State(pool.clone()),
+            admin.clone(),
+            ValidJson(CreateQuote {
+                book: "Dune".to_string(),
+                quote: quote.to_string(),
+                author_id: None,
+            }),
+        )
+        .await;
+        dune.push(res.unwrap().1 .0.id);
+    }
+    let mut by_id = dune.clone();
+    by_id.sort();
+    let only_dune = |query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>| {
+        query.push(" AND book = 'Dune'");
+        Ok(())
+    };
+    let mut conn = pool.acquire().await?;
+    for (draws, expected) in [
+        (&[0.0, 0.5, 0.0][..], dune[0]),
+        (&[0.0, 0.9, 0.0], dune[1]),
+        // when no draw passes, the last one is taken along the quotes that do
+        (&[0.0, 0.0, 0.49], by_id[0]),
+        (&[0.0, 0.0, 0.5], by_id[1]),
+    ] {
+        let quote = pick_quote(&mut conn, draws, only_dune).await;
+        assert_eq!(quote.unwrap().unwrap().id, expected);
+    }
+    // the last quote takes the number of one that leaves
+    sqlx::query("UPDATE quotes SET deleted_at = now() WHERE id = $1")
+        .bind(dune[0])
+        .execute(&pool)
+        .await?;
+    let quote = pick_quote(&mut conn, &[0.5, 0.0], only_dune).await;
+    assert_eq!(quote.unwrap().unwrap().id, dune[1]);
+    let res = random_quote(
+        extract::State(pool),
+        ValidQuery(RandomQuote {
+            tag: Some("horror".to_string()),
+            ..Default::default()
+        }),
+    )
+    .await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::NOT_FOUND)
+    );
+    Ok(())
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_daily_quote(pool: PgPool) -> sqlx::Result<()> {
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
+    for quote in [
+        "Go where you must go.",
+        "All that is gold does not glitter.",
+        "Not all those who wander are lost.",
+    ] {
+        let res = create_quote(
+            extract::State(pool.clone()),
+            admin.clone(),
+            ValidJson(CreateQuote {
+                book: "The Hobbit".to_string(),
+                quote: quote.to_string(),
+                author_id: None,
+            }),
+        )
+        .await;
+        assert!(res.is_ok());
+    }
+    sqlx::query("UPDATE quotes SET inserted_at = now() - interval '1 day' WHERE book = 'The Hobbit' AND inserted_at > now() - interval '1 day'")
+        .execute(&pool)
+        .await?;
+    let daily = |date| {
+        daily_quote(
+            extract::State(pool.clone()),
+            ValidQuery(DailyQuote { date }),
+        )
+    };
+    // quotes added on the day itself are not eligible
+    let date = chrono::NaiveDate::from_ymd_opt(2021, 1, 1);
+    assert_eq!(
+        daily(date).await.unwrap().0.id,
+        uuid::Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap()
+    );
+    let date = chrono::NaiveDate::from_ymd_opt(2020, 1, 1);
+    assert_eq!(
+        daily(date).await.err().map(|err| err.status()),
+        Some(http::StatusCode::NOT_FOUND)
+    );
+    // the same date always gives the same quote, even once others are
+    // deleted, and another one only once it is deleted itself
+    let today = daily(None).await.unwrap().0;
+    assert_eq!(daily(None).await.unwrap().0.id, today.id);
+    let others = sqlx::query_scalar::<_, uuid::Uuid>("SELECT id FROM quotes WHERE id <> $1")
+        .bind(today.id)
+        .fetch_all(&pool)
+        .await?;
+    sqlx::query("UPDATE quotes SET deleted_at = now() WHERE id = $1")
+        .bind(others[0])
+        .execute(&pool)
+        .await?;
+    sqlx::query("DELETE FROM quotes WHERE id = $1")
+        .bind(others[1])
+        .execute(&pool)
+        .await?;
+    assert_eq!(daily(None).await.unwrap().0.id, today.id);
+    sqlx::query("UPDATE quotes SET deleted_at = now() WHERE id = $1")
+        .bind(today.id)
+        .execute(&pool)
+        .await?;
+    assert_ne!(daily(None).await.unwrap().0.id, today.id);
+    Ok(())
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_read_quote(pool: PgPool) -> sqlx::Result<()> {
+    let id = uuid::Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap();
+    let res = read_quote(
+        extract::State(pool.clone()),
+        ValidPath(id),
+        http::HeaderMap::new(),
+    )
+    .await;
+    let res = res.unwrap();
+    assert_eq!(res.status(), http::StatusCode::OK);
+    let etag = res.headers()[http::header::ETAG].clone();
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let quote: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(quote["id"], id.to_string());
+    assert_eq!(quote["book"], "The Hobbit");
+    // a client holding the current version is told it has not changed
+    let mut headers = http::HeaderMap::new();
+    headers.insert(http::header::IF_NONE_MATCH, etag);
+    let res = read_quote(extract::State(pool.clone()), ValidPath(id), headers).await;
+    assert_eq!(res.unwrap().status(), http::StatusCode::NOT_MODIFIED);
+    // an unknown id is reported as not found
+    let res = read_quote(
+        extract::State(pool),
+        ValidPath(uuid::Uuid::new_v4()),
+        http::HeaderMap::new(),
+    )
+    .await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::NOT_FOUND)
+    );
     Ok(())
 }
 
 #[sqlx::test(fixtures("quotes"))]
 async fn test_update_quotes(pool: PgPool) -> sqlx::Result<()> {
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
     let res = update_quote(
         extract::State(pool.clone()),
-        extract::Path(uuid::Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap()),
-        axum::Json(CreateQuote {
+        ValidPath(uuid::Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap()),
+        admin.clone(),
+        http::HeaderMap::new(),
+        ValidJson(CreateQuote {
             book: "book".to_string(),
             quote: "quote".to_string(),
+            author_id: None,
         }),
     )
     .await;
-    assert_eq!(res, http::StatusCode::OK);
+    let quote = res.unwrap().0;
+    assert_eq!(quote.book, "book");
+    assert!(quote.updated_at > quote.inserted_at);
+    // a write based on the old version is refused
+    let mut headers = http::HeaderMap::new();
+    headers.insert(http::header::IF_MATCH, etag::etag(quote.inserted_at));
+    let res = update_quote(
+        extract::State(pool.clone()),
+        ValidPath(quote.id),
+        admin.clone(),
+        headers,
+        ValidJson(CreateQuote {
+            book: "stale".to_string(),
+            quote: "quote".to_string(),
+            author_id: None,
+        }),
+    )
+    .await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::PRECONDITION_FAILED)
+    );
     // verify that the quote was updated
-    let res = read_quotes(extract::State(pool)).await;
+    let res = read_quotes(extract::State(pool), ListQuotes::default()).await;
     assert!(res.is_ok());
     let quotes = res.unwrap();
-    assert_eq!(quotes.0.len(), 1);
-    assert_eq!(quotes.0[0].book, "book");
+    assert_eq!(quotes.0.data.len(), 1);
+    assert_eq!(quotes.0.data[0].book, "book");
+    Ok(())
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_patch_quote(pool: PgPool) -> sqlx::Result<()> {
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
+    let id = uuid::Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap();
+    let res = patch_quote(
+        extract::State(pool.clone()),
+        ValidPath(id),
+        admin.clone(),
+        http::HeaderMap::new(),
+        ValidJson(PatchQuote {
+            book: Some("The Hobbit, or There and Back Again".to_string()),
+            quote: None,
+            author_id: None,
+        }),
+    )
+    .await;
+    let quote = res.unwrap().0;
+    assert_eq!(quote.book, "The Hobbit, or There and Back Again");
+    // fields left out of the payload keep their value
+    assert_eq!(quote.quote, "In a hole in the ground there lived a hobbit.");
+    assert!(quote.updated_at > quote.inserted_at);
+    // an author is cleared by setting it to null, and kept when left out
+    let author_id = uuid::Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO authors (id, name, inserted_at, updated_at) VALUES ($1, $2, now(), now())",
+    )
+    .bind(author_id)
+    .bind("J. R. R. Tolkien")
+    .execute(&pool)
+    .await?;
+    let patch = |payload| {
+        patch_quote(
+            extract::State(pool.clone()),
+            ValidPath(id),
+            admin.clone(),
+            http::HeaderMap::new(),
+            ValidJson(serde_json::from_value(payload).unwrap()),
+        )
+    };
+    let quote = patch(serde_json::json!({ "author_id": author_id })).await;
+    assert_eq!(quote.unwrap().0.author_id, Some(author_id));
+    let quote =
+        patch(serde_json::json!({ "quote": "In a hole in the ground lived a hobbit." })).await;
+    assert_eq!(quote.unwrap().0.author_id, Some(author_id));
+    let quote = patch(serde_json::json!({ "author_id": null })).await;
+    assert_eq!(quote.unwrap().0.author_id, None);
+    let res = patch_quote(
+        extract::State(pool),
+        ValidPath(uuid::Uuid::new_v4()),
+        admin.clone(),
+        http::HeaderMap::new(),
+        ValidJson(PatchQuote::default()),
+    )
+    .await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::NOT_FOUND)
+    );
+    Ok(())
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_replace_tags(pool: PgPool) -> sqlx::Result<()> {
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
+    let id = uuid::Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap();
+    let mut payload = ReplaceTags {
+        tags: vec![
+            "Fantasy".to_string(),
+            " fantasy ".to_string(),
+            "Opening lines".to_string(),
+        ],
+    };
+    payload.validate(&Limits::default()).unwrap();
+    let res = replace_tags(
+        extract::State(pool.clone()),
+        ValidPath(id),
+        admin.clone(),
+        http::HeaderMap::new(),
+        ValidJson(payload),
+    )
+    .await;
+    // tags are lowercased and deduplicated
+    let quote = res.unwrap().0;
+    assert_eq!(quote.tags, ["fantasy", "opening lines"]);
+    assert!(quote.updated_at > quote.inserted_at);
+    let list = |tag: &str, tag_match| {
+        read_quotes(
+            extract::State(pool.clone()),
+            ListQuotes {
+                tag: Some(tag.to_string()),
+                tag_match,
+                ..Default::default()
+            },
+        )
+    };
+    let page = list("FANTASY,horror", None).await.unwrap();
+    assert_eq!(page.data.len(), 1);
+    let page = list("fantasy,horror", Some(TagMatch::All)).await.unwrap();
+    assert!(page.data.is_empty());
+    let page = list("opening lines,fantasy", Some(TagMatch::All))
+        .await
+        .unwrap();
+    assert_eq!(page.data.len(), 1);
+    // an empty set removes every tag
+    let res = replace_tags(
+        extract::State(pool.clone()),
+        ValidPath(id),
+        admin.clone(),
+        http::HeaderMap::new(),
+        ValidJson(ReplaceTags { tags: vec![] }),
+    )
+    .await;
+    assert!(res.unwrap().0.tags.is_empty());
+    let mut payload = ReplaceTags {
+        tags: vec![" ".to_string()],
+    };
+    assert_eq!(
+        payload.validate(&Limits::default()).unwrap_err()[0].field,
+        "tags"
+    );
     Ok(())
 }
 
 #[sqlx::test(fixtures("quotes"))]
 async fn test_delete_quote(pool: PgPool) -> sqlx::Result<()> {
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
     let res = delete_quote(
         extract::State(pool.clone()),
-        extract::Path(uuid::Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap()),
+        ValidPath(uuid::Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap()),
+        admin.clone(),
+        http::HeaderMap::new(),
     )
     .await;
-    assert_eq!(res, http::StatusCode::OK);
+    // the removed quote is returned
+    let res = res.unwrap();
+    assert_eq!(res.status(), http::StatusCode::OK);
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let removed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(removed["book"], "The Hobbit");
     // verify that the quote was deleted
-    let res = read_quotes(extract::State(pool)).await;
+    let res = read_quotes(extract::State(pool.clone()), ListQuotes::default()).await;
     assert!(res.is_ok());
     let quotes = res.unwrap();
-    assert_eq!(quotes.0.len(), 0);
+    assert_eq!(quotes.0.data.len(), 0);
+    // a minimal response has no body
+    let (_, axum::Json(quote)) = create_quote(
+        extract::State(pool.clone()),
+        admin.clone(),
+        ValidJson(CreateQuote {
+            book: "book".to_string(),
+            quote: "quote".to_string(),
+            author_id: None,
+        }),
+    )
+    .await
+    .unwrap();
+    let mut headers = http::HeaderMap::new();
+    headers.insert("prefer", http::HeaderValue::from_static("return=minimal"));
+    let res = delete_quote(
+        extract::State(pool),
+        ValidPath(quote.id),
+        admin.clone(),
+        headers,
+    )
+    .await;
+    assert_eq!(res.unwrap().status(), http::StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_trash_and_restore(pool: PgPool) -> sqlx::Result<()> {
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
+    let id = uuid::Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap();
+    let res = delete_quote(
+        extract::State(pool.clone()),
+        ValidPath(id),
+        admin.clone(),
+        http::HeaderMap::new(),
+    )
+    .await;
+    assert!(res.is_ok());
+    // the deleted quote is in the trash and can no longer be read
+    let res = read_trash(extract::State(pool.clone()), ListQuotes::default()).await;
+    let trash = res.unwrap().0.data;
+    assert_eq!(trash.len(), 1);
+    assert!(trash[0].deleted_at.is_some());
+    let res = read_quote(
+        extract::State(pool.clone()),
+        ValidPath(id),
+        http::HeaderMap::new(),
+    )
+    .await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::NOT_FOUND)
+    );
+    let res = restore_quote(extract::State(pool.clone()), ValidPath(id), admin.clone()).await;
+    assert!(res.unwrap().0.deleted_at.is_none());
+    let res = read_quotes(extract::State(pool.clone()), ListQuotes::default()).await;
+    assert_eq!(res.unwrap().0.data.len(), 1);
+    // only quotes in the trash can be restored
+    let res = restore_quote(extract::State(pool.clone()), ValidPath(id), admin.clone()).await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::NOT_FOUND)
+    );
+    // a trashed quote does not block adding it again, but then cannot be restored
+    let res = delete_quote(
+        extract::State(pool.clone()),
+        ValidPath(id),
+        admin.clone(),
+        http::HeaderMap::new(),
+    )
+    .await;
+    assert!(res.is_ok());
+    let (_, axum::Json(quote)) = create_quote(
+        extract::State(pool.clone()),
+        admin.clone(),
+        ValidJson(CreateQuote {
+            book: "The Hobbit".to_string(),
+            quote: "In a hole in the ground there lived a hobbit.".to_string(),
+            author_id: None,
+        }),
+    )
+    .await
+    .unwrap();
+    let res = restore_quote(extract::State(pool), ValidPath(id), admin.clone()).await;
+    match res {
+        Err(AppError::Conflict { existing_id }) => assert_eq!(existing_id, quote.id),
+        _ => panic!("expected a conflict"),
+    }
+    Ok(())
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_quote_owners(pool: PgPool) -> sqlx::Result<()> {
+    use crate::auth::caller;
+
+    let (alice, bob) = (
+        caller("alice", crate::auth::Scope::Write),
+        caller("bob", crate::auth::Scope::Write),
+    );
+    let (_, axum::Json(quote)) = create_quote(
+        extract::State(pool.clone()),
+        alice.clone(),
+        ValidJson(CreateQuote {
+            book: "The Hobbit".to_string(),
+            quote: "Where there's life there's hope.".to_string(),
+            author_id: None,
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(quote.created_by.as_deref(), Some("alice"));
+    // only the caller that added a quote may change it
+    let res = patch_quote(
+        extract::State(pool.clone()),
+        ValidPath(quote.id),
+        bob.clone(),
+        http::HeaderMap::new(),
+        ValidJson(PatchQuote {
+            quote: Some("Where there's life there's hope, and need of vittles.".to_string()),
+            ..Default::default()
+        }),
+    )
+    .await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::FORBIDDEN)
+    );
+    let res = delete_quote(
+        extract::State(pool.clone()),
+        ValidPath(quote.id),
+        bob.clone(),
+        http::HeaderMap::new(),
+    )
+    .await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::FORBIDDEN)
+    );
+    // quotes from before owners were recorded are left to admins
+    let legacy = uuid::Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap();
+    let res = delete_quote(
+        extract::State(pool.clone()),
+        ValidPath(legacy),
+        alice.clone(),
+        http::HeaderMap::new(),
+    )
+    .await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::FORBIDDEN)
+    );
+    let res = delete_quote(
+        extract::State(pool.clone()),
+        ValidPath(quote.id),
+        caller("carol", crate::auth::Scope::Admin),
+        http::HeaderMap::new(),
+    )
+    .await;
+    assert!(res.is_ok());
+
+    let list = |caller: Option<Caller>| {
+        let mut parts = http::Request::get("/quotes/trash?mine=true")
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0;
+        if let Some(caller) = caller {
+            parts.extensions.insert(caller);
+        }
+        let pool = pool.clone();
+        async move {
+            let params =
+                <ListQuotes as extract::FromRequestParts<()>>::from_request_parts(&mut parts, &())
+                    .await?;
+            read_trash(extract::State(pool), params).await
+        }
+    };
+    let page = list(Some(alice)).await.unwrap().0;
+    assert_eq!(page.data.len(), 1);
+    assert_eq!(page.data[0].id, quote.id);
+    let page = list(Some(bob)).await.unwrap().0;
+    assert!(page.data.is_empty());
+    let res = list(None).await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::UNAUTHORIZED)
+    );
+    Ok(())
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_bulk_quotes(pool: PgPool) -> sqlx::Result<()> {
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
+    let request = |mode: &str| -> BulkRequest {
+        serde_json::from_value(serde_json::json!({
+            "mode": mode,
+            "operations": [
+                {"op": "create", "book": "Dune", "quote": "Fear is the mind-killer."},
+                {"op": "create", "book": "The Hobbit", "quote": "In a hole in the ground there lived a hobbit."},
+                {"op": "create", "book": " ", "quote": "quote"},
+                {"op": "delete", "id": "a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11"},
+            ]
+        }))
+        .unwrap()
+    };
+    let res = bulk_quotes(
+        extract::State(pool.clone()),
+        extract::State(Limits::default()),
+        admin.clone(),
+        ValidJson(request("all_or_nothing")),
+    )
+    .await;
+    let res = res.unwrap().0;
+    assert!(!res.committed);
+    let statuses: Vec<_> = res.results.iter().map(|res| res.status).collect();
+    assert_eq!(statuses, [424, 409, 424, 424]);
+    // nothing was written
+    let res = read_quotes(extract::State(pool.clone()), ListQuotes::default()).await;
+    assert_eq!(res.unwrap().0.data.len(), 1);
+
+    let res = bulk_quotes(
+        extract::State(pool.clone()),
+        extract::State(Limits::default()),
+        admin.clone(),
+        ValidJson(request("best_effort")),
+    )
+    .await;
+    let res = res.unwrap().0;
+    assert!(res.committed);
+    let statuses: Vec<_> = res.results.iter().map(|res| res.status).collect();
+    assert_eq!(statuses, [201, 409, 422, 200]);
+    let res = read_quotes(extract::State(pool), ListQuotes::default()).await;
+    let quotes = res.unwrap().0.data;
+    assert_eq!(quotes.len(), 1);
+    assert_eq!(quotes[0].book, "Dune");
     Ok(())
 }
@@ -0,0 +1,152 @@
+use crate::error;
+use axum::response::{IntoResponse, Response};
+use axum::{extract, http};
+use serde::Serialize;
+use sqlx::migrate::Migrator;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// How long each readiness check may take before it counts as failed.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+const UNDEFINED_TABLE: &str = "42P01";
+
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+#[derive(Serialize, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Ok,
+    Unavailable,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Check {
+    status: Status,
+    latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Readiness {
+    status: Status,
+    checks: HashMap<&'static str, Check>,
+}
+
+/// Runs one readiness check, failing it when it errs or outlasts `CHECK_TIMEOUT`.
+async fn check(probe: impl Future<Output = Result<(), String>>) -> Check {
+    let start = Instant::now();
+    let res = match tokio::time::timeout(CHECK_TIMEOUT, probe).await {
+        Ok(res) => res,
+        Err(_) => Err(format!("timed out after {:?}", CHECK_TIMEOUT)),
+    };
+    Check {
+        status: if res.is_ok() {
+            Status::Ok
+        } else {
+            Status::Unavailable
+        },
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+        error: res.err(),
+    }
+}
+
+/// Whether every migration this build ships with has been applied as is.
+async fn migrations_current(pool: &PgPool) -> Result<(), String> {
+    let applied = sqlx::query_as::<_, (i64, Vec<u8>)>(
+        "SELECT version, checksum FROM _sqlx_migrations WHERE success",
+    )
+    .fetch_all(pool)
+    .await;
+    let applied: HashMap<i64, Vec<u8>> = match applied {
+        Ok(applied) => applied.into_iter().collect(),
+        // The database was never migrated.
+        Err(err) if error::has_code(&err, UNDEFINED_TABLE) => HashMap::new(),
+        Err(err) => return Err(err.to_string()),
+    };
+    let mut pending = Vec::new();
+    for migration in MIGRATOR.iter() {
+        match applied.get(&migration.version) {
+            Some(checksum) if *checksum == *migration.checksum => {}
+            Some(_) => return Err(format!("migration {} was modified", migration.version)),
+            None => pending.push(migration.version.to_string()),
+        }
+    }
+    if pending.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("migrations {} are pending", pending.join(", ")))
+    }
+}
+
+/// The process is up; says nothing of its dependencies.
+pub async fn livez() -> axum::Json<serde_json::Value> {
+    axum::Json(serde_json::json!({ "status": Status::Ok }))
+}
+
+/// Whether the instance can serve requests: the database answers and its
+/// schema is the one this build expects. Responds 503 when it cannot.
+pub async fn readyz(extract::State(pool): extract::State<PgPool>) -> Response {
+    let database = check(async {
+        sqlx::query("SELECT 1")
+            .execute(&pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    })
+    .await;
+    let migrations = check(migrations_current(&pool)).await;
+    let checks = HashMap::from([("database", database), ("migrations", migrations)]);
+    let status = if checks.values().all(|check| check.status == Status::Ok) {
+        Status::Ok
+    } else {
+        Status::Unavailable
+    };
+    let code = match status {
+        Status::Ok => http::StatusCode::OK,
+        Status::Unavailable => http::StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (code, axum::Json(Readiness { status, checks })).into_response()
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_readyz(pool: PgPool) -> sqlx::Result<()> {
+    let probe = |pool: PgPool| async {
+        let res = readyz(extract::State(pool)).await;
+        let status = res.status();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+        )
+    };
+    let (status, body) = probe(pool.clone()).await;
+    assert_eq!(status, http::StatusCode::OK);
+    assert_eq!(body["checks"]["database"]["status"], "ok");
+    assert!(body["checks"]["migrations"]["latency_ms"].is_number());
+
+    // a migration that was not applied keeps the instance out of rotation
+    let latest = MIGRATOR.iter().map(|migration| migration.version).max();
+    sqlx::query("DELETE FROM _sqlx_migrations WHERE version = $1")
+        .bind(latest)
+        .execute(&pool)
+        .await?;
+    let (status, body) = probe(pool.clone()).await;
+    assert_eq!(status, http::StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "unavailable");
+    assert_eq!(body["checks"]["database"]["status"], "ok");
+    assert_eq!(
+        body["checks"]["migrations"]["error"],
+        format!("migrations {} are pending", latest.unwrap())
+    );
+
+    pool.close().await;
+    let (status, body) = probe(pool).await;
+    assert_eq!(status, http::StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["checks"]["database"]["status"], "unavailable");
+    Ok(())
+}
@@ -1,16 +1,41 @@
+mod auth;
+mod authors;
+mod books;
+mod config;
+mod error;
+mod etag;
 mod handlers;
-use axum::routing::{delete, get, post, put, Router};
+mod health;
+mod metrics;
+mod pagination;
+mod ratelimit;
+mod request_id;
+mod revisions;
+mod telemetry;
+mod trash;
+mod validation;
+use axum::extract::FromRef;
+use axum::http;
+use axum::middleware;
+use axum::routing::{delete, get, patch, post, put, Router};
 use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
 use tower_http::trace::TraceLayer;
 use tower_http::trace::{self};
 use tracing::Level;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[derive(Clone, FromRef)]
+struct AppState {
+    pool: PgPool,
+    limits: validation::Limits,
+    auth: auth::AuthConfig,
+    rate_limiter: ratelimit::RateLimiter,
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt()
-        .with_target(false)
-        .compact()
-        .init();
+    let tracer_provider = telemetry::init();
     let port = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string());
 
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
@@ -19,23 +44,124 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .connect(&database_url)
         .await?;
 
-    let app = Router::new()
-        .route("/", get(handlers::health))
-        .route("/quotes", post(handlers::create_quote))
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return auth::run_command(&pool, &args).await;
+    }
+
+    trash::spawn_purge(pool.clone(), trash::retention_from_env());
+    metrics::spawn_refresh(pool.clone());
+
+    let state = AppState {
+        pool,
+        limits: validation::Limits::from_env(),
+        auth: auth::AuthConfig::from_env(),
+        rate_limiter: ratelimit::RateLimiter::new(ratelimit::RateLimits::from_env()),
+    };
+    ratelimit::spawn_prune(state.rate_limiter.clone());
+    let reads = Router::new()
+        .route("/authors", get(authors::read_authors))
+        .route("/authors/:id", get(authors::read_author))
+        .route("/authors/:id/quotes", get(authors::read_author_quotes))
+        .route("/books", get(books::read_books))
+        .route("/books/:id", get(books::read_book))
+        .route("/books/:id/quotes", get(books::read_book_quotes))
         .route("/quotes", get(handlers::read_quotes))
+        .route("/quotes/daily", get(handlers::daily_quote))
+        .route("/quotes/random", get(handlers::random_quote))
+        .route("/quotes/search", get(handlers::search_quotes))
+        .route("/quotes/:id", get(handlers::read_quote))
+        .route("/quotes/:id/revisions", get(revisions::read_revisions))
+        .route("/quotes/:id/revisions/:n", get(revisions::read_revision))
+        .route_layer(middleware::from_fn_with_state(
+            auth::Scope::Read,
+            auth::require_scope,
+        ));
+    let writes = Router::new()
+        .route("/authors", post(authors::create_author))
+        .route("/authors/:id", put(authors::update_author))
+        .route("/books", post(books::create_book))
+        .route("/books/:id", put(books::update_book))
+        .route("/quotes", post(handlers::create_quote))
+        .route("/quotes/bulk", post(handlers::bulk_quotes))
+        .route("/quotes/trash", get(handlers::read_trash))
         .route("/quotes/:id", put(handlers::update_quote))
+        .route("/quotes/:id", patch(handlers::patch_quote))
         .route("/quotes/:id", delete(handlers::delete_quote))
+        .route("/quotes/:id/restore", post(handlers::restore_quote))
+        .route("/quotes/:id/revert/:n", post(revisions::revert_quote))
+        .route("/quotes/:id/tags", put(handlers::replace_tags))
+        .route_layer(middleware::from_fn_with_state(
+            auth::Scope::Write,
+            auth::require_scope,
+        ));
+    let admin = Router::new()
+        .route("/authors/:id", delete(authors::delete_author))
+        .route("/books/:id", delete(books::delete_book))
+        .route_layer(middleware::from_fn_with_state(
+            auth::Scope::Admin,
+            auth::require_scope,
+        ));
+    let app = Router::new()
+        .merge(reads)
+        .merge(writes)
+        .merge(admin)
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            ratelimit::limit,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::authenticate,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            ratelimit::limit_failed_auth,
+        ))
+        .route_layer(middleware::from_fn(metrics::track))
+        .route("/", get(handlers::health))
+        .route("/livez", get(health::livez))
+        .route("/readyz", get(health::readyz))
+        .route("/metrics", get(metrics::render))
         .layer(
             TraceLayer::new_for_http()
-                .make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
+                .make_span_with(|request: &http::Request<_>| {
+                    // Set by `request_id::propagate`, which runs first.
+                    let request_id = request
+                        .headers()
+                        .get(request_id::REQUEST_ID_HEADER)
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or_default();
+                    let span = tracing::info_span!(
+                        "request",
+                        method = %request.method(),
+                        uri = %request.uri(),
+                        version = ?request.version(),
+                        request_id,
+                        api_key_id = tracing::field::Empty,
+                        subject = tracing::field::Empty,
+                    );
+                    span.set_parent(telemetry::parent_context(request.headers()));
+                    span
+                })
                 .on_response(trace::DefaultOnResponse::new().level(Level::INFO)),
         )
-        .with_state(pool);
+        .layer(middleware::from_fn(request_id::propagate))
+        .with_state(state);
 
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
         .await
         .unwrap();
     tracing::info!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    // Anonymous clients are rate limited by their address.
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .await
+    .unwrap();
+    if let Some(provider) = tracer_provider {
+        provider.shutdown()?;
+    }
     Ok(())
 }
@@ -0,0 +1,233 @@
+use crate::telemetry::Observe;
+use axum::extract::{MatchedPath, Request};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use sqlx::pool::PoolConnection;
+use sqlx::{PgPool, Postgres};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(15);
+
+tokio::task_local! {
+    /// The route of the request being served, for labelling its queries.
+    static ROUTE: String;
+}
+
+/// Everything exposed on `/metrics`. Request, query and connection wait
+/// metrics accumulate as they happen; the pool and quote gauges are
+/// refreshed in the background.
+struct Collectors {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    query_duration: HistogramVec,
+    pool_acquire_duration: HistogramVec,
+    pool_connections: IntGauge,
+    pool_idle_connections: IntGauge,
+    pool_max_connections: IntGauge,
+    quotes: IntGaugeVec,
+}
+
+impl Collectors {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Requests served"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "Time to serve a request"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let query_duration = HistogramVec::new(
+            HistogramOpts::new("db_query_duration_seconds", "Time to run a query").buckets(vec![
+                0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+            ]),
+            &["route", "statement"],
+        )
+        .unwrap();
+        let pool_acquire_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "db_pool_acquire_duration_seconds",
+                "Time to get a connection from the pool",
+            )
+            .buckets(vec![
+                0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5,
+                1.0, 2.5, 5.0,
+            ]),
+            &["route"],
+        )
+        .unwrap();
+        let pool_connections =
+            IntGauge::new("db_pool_connections", "Connections open in the pool").unwrap();
+        let pool_idle_connections =
+            IntGauge::new("db_pool_idle_connections", "Open connections not in use").unwrap();
+        let pool_max_connections =
+            IntGauge::new("db_pool_max_connections", "Most connections the pool opens").unwrap();
+        let quotes = IntGaugeVec::new(
+            Opts::new("quotes", "Quotes stored, live or in the trash"),
+            &["state"],
+        )
+        .unwrap();
+        for collector in [
+            Box::new(requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(request_duration.clone()),
+            Box::new(query_duration.clone()),
+            Box::new(pool_acquire_duration.clone()),
+            Box::new(pool_connections.clone()),
+            Box::new(pool_idle_connections.clone()),
+            Box::new(pool_max_connections.clone()),
+            Box::new(quotes.clone()),
+        ] {
+            registry.register(collector).unwrap();
+        }
+        Self {
+            registry,
+            requests,
+            request_duration,
+            query_duration,
+            pool_acquire_duration,
+            pool_connections,
+            pool_idle_connections,
+            pool_max_connections,
+            quotes,
+        }
+    }
+}
+
+static COLLECTORS: LazyLock<Collectors> = LazyLock::new(Collectors::new);
+
+/// The route of the request being served, or `background` outside of one.
+fn route() -> String {
+    ROUTE
+        .try_with(Clone::clone)
+        .unwrap_or_else(|_| "background".to_string())
+}
+
+/// Takes a connection from the pool, timing the wait for it in
+/// `db_pool_acquire_duration_seconds` under the route of the request.
+/// Handlers run their queries on it rather than on the pool so that the
+/// wait is seen where it is felt.
+pub async fn acquire(pool: &PgPool) -> sqlx::Result<PoolConnection<Postgres>> {
+    let start = Instant::now();
+    let conn = pool.acquire().await;
+    COLLECTORS
+        .pool_acquire_duration
+        .with_label_values(&[&route()])
+        .observe(start.elapsed().as_secs_f64());
+    conn
+}
+
+/// Times a query in `db_query_duration_seconds` under the route of the
+/// request that ran it. Queries run outside of a request count under the
+/// `background` route.
+pub fn record_query(statement: &str, duration: Duration) {
+    COLLECTORS
+        .query_duration
+        .with_label_values(&[&route(), statement])
+        .observe(duration.as_secs_f64());
+}
+
+/// Route layer that counts and times requests by method, route and status.
+pub async fn track(matched: Option<MatchedPath>, request: Request, next: Next) -> Response {
+    let route = matched.map_or_else(String::new, |path| path.as_str().to_string());
+    let method = request.method().clone();
+    let start = Instant::now();
+    let res = ROUTE.scope(route.clone(), next.run(request)).await;
+    let status = res.status().as_str().to_string();
+    let labels = [method.as_str(), &route, &status];
+    COLLECTORS.requests.with_label_values(&labels).inc();
+    COLLECTORS
+        .request_duration
+        .with_label_values(&labels)
+        .observe(start.elapsed().as_secs_f64());
+    res
+}
+
+/// Updates the pool and quote gauges.
+pub async fn refresh(pool: &PgPool) -> sqlx::Result<()> {
+    let collectors = &*COLLECTORS;
+    collectors.pool_connections.set(pool.size().into());
+    collectors.pool_idle_connections.set(pool.num_idle() as i64);
+    collectors
+        .pool_max_connections
+        .set(pool.options().get_max_connections().into());
+    let (live, trashed) = sqlx::query_as::<_, (i64, i64)>(
+        r#"
+        SELECT count(*) FILTER (WHERE deleted_at IS NULL),
+            count(*) FILTER (WHERE deleted_at IS NOT NULL)
+        FROM quotes
+        "#,
+    )
+    .fetch_one(pool)
+    .observe_one("count_quotes")
+    .await?;
+    collectors.quotes.with_label_values(&["live"]).set(live);
+    collectors.quotes.with_label_values(&["trash"]).set(trashed);
+    Ok(())
+}
+
+/// Runs `refresh` in the background every 15 seconds, so that scrapes,
+/// which anyone may request, never touch the database.
+pub fn spawn_refresh(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(err) = refresh(&pool).await {
+                tracing::error!(error = %err, "failed to refresh the metrics");
+            }
+        }
+    });
+}
+
+/// Renders every metric in the Prometheus text format.
+pub async fn render() -> Response {
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    encoder
+        .encode(&COLLECTORS.registry.gather(), &mut body)
+        .expect("metrics are encoded into memory");
+    (
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        body,
+    )
+        .into_response()
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_metrics(pool: PgPool) -> sqlx::Result<()> {
+    sqlx::query("UPDATE quotes SET deleted_at = now()")
+        .execute(&pool)
+        .observe("trash_everything")
+        .await?;
+    refresh(&pool).await?;
+    ROUTE
+        .scope("/test/metrics".to_string(), acquire(&pool))
+        .await?;
+    let res = render().await;
+    assert_eq!(
+        res.headers()[header::CONTENT_TYPE],
+        "text/plain; version=0.0.4"
+    );
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("quotes{state=\"live\"} 0"));
+    assert!(body.contains("quotes{state=\"trash\"} 1"));
+    assert!(body.contains(
+        "db_query_duration_seconds_count{route=\"background\",statement=\"trash_everything\"} 1"
+    ));
+    assert!(body.contains("db_pool_acquire_duration_seconds_count{route=\"/test/metrics\"} 1"));
+    assert!(body.contains("db_pool_max_connections"));
+    Ok(())
+}
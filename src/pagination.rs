@@ -0,0 +1,56 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Serialize;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 100;
+
+/// The requested page size, bounded by `MAX_PAGE_SIZE`.
+pub fn page_size(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+#[derive(Serialize)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Builds a page from rows fetched with a limit of `limit + 1`; the extra
+    /// row only tells whether another page follows, and `cursor` is asked
+    /// for the position of the last row kept in that case.
+    pub fn from_rows(mut rows: Vec<T>, limit: i64, cursor: impl FnOnce(&T) -> String) -> Self {
+        let next_cursor = if rows.len() as i64 > limit {
+            rows.truncate(limit as usize);
+            rows.last().map(cursor)
+        } else {
+            None
+        };
+        Self {
+            data: rows,
+            next_cursor,
+        }
+    }
+}
+
+/// Position of the last row on a page ordered by `(lower(name), id)`.
+pub struct NameCursor {
+    pub id: uuid::Uuid,
+    pub name: String,
+}
+
+impl NameCursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}|{}", self.id, self.name))
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        let raw = String::from_utf8(bytes).ok()?;
+        let (id, name) = raw.split_once('|')?;
+        Some(Self {
+            id: uuid::Uuid::parse_str(id).ok()?,
+            name: name.to_string(),
+        })
+    }
+}
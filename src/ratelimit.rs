@@ -0,0 +1,378 @@
+use crate::auth::{self, Caller};
+use crate::config::env_number;
+use crate::error::AppError;
+use axum::extract::{self, ConnectInfo, Request};
+use axum::http::{self, HeaderValue};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const DEFAULT_READS_PER_MINUTE: u32 = 300;
+const DEFAULT_WRITES_PER_MINUTE: u32 = 60;
+/// Buckets refill within a minute, so pruning as often keeps only the ones
+/// of clients seen in the last two minutes or so.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Requests that only read get a budget apart from the ones that write, so
+/// that a client busy writing can still read.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Budget {
+    Read,
+    Write,
+}
+
+impl Budget {
+    fn of(method: &http::Method) -> Self {
+        match *method {
+            http::Method::GET | http::Method::HEAD | http::Method::OPTIONS => Budget::Read,
+            _ => Budget::Write,
+        }
+    }
+}
+
+/// Requests allowed per client and minute, for each budget.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimits {
+    pub reads_per_minute: u32,
+    pub writes_per_minute: u32,
+    /// Whether anonymous clients are told apart by the first address in
+    /// `X-Forwarded-For`, which only a trusted proxy in front can vouch for.
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            reads_per_minute: DEFAULT_READS_PER_MINUTE,
+            writes_per_minute: DEFAULT_WRITES_PER_MINUTE,
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+impl RateLimits {
+    /// Reads `RATE_LIMIT_READS_PER_MINUTE` and `RATE_LIMIT_WRITES_PER_MINUTE`,
+    /// where 0 turns the limit off, and `RATE_LIMIT_TRUST_FORWARDED_FOR`.
+    pub fn from_env() -> Self {
+        Self {
+            reads_per_minute: env_number("RATE_LIMIT_READS_PER_MINUTE", DEFAULT_READS_PER_MINUTE),
+            writes_per_minute: env_number(
+                "RATE_LIMIT_WRITES_PER_MINUTE",
+                DEFAULT_WRITES_PER_MINUTE,
+            ),
+            trust_forwarded_for: std::env::var("RATE_LIMIT_TRUST_FORWARDED_FOR")
+                .is_ok_and(|value| value == "true"),
+        }
+    }
+
+    fn per_minute(&self, budget: Budget) -> u32 {
+        match budget {
+            Budget::Read => self.reads_per_minute,
+            Budget::Write => self.writes_per_minute,
+        }
+    }
+}
+
+/// A token bucket that holds up to a minute's worth of requests and refills
+/// continuously, so a client may burst that many and then keeps to the rate.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// What a client has left of a budget after a request.
+#[derive(PartialEq, Debug)]
+pub struct Quota {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again.
+    pub reset: u64,
+    /// Seconds until the next request is allowed, when this one was refused.
+    pub retry_after: Option<u64>,
+}
+
+/// Token buckets per client and budget, shared by every request.
+#[derive(Clone)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: Arc<Mutex<HashMap<(String, Budget), Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            buckets: Arc::default(),
+        }
+    }
+
+    /// Takes a token for one request of `client` at `now`, or tells how long
+    /// to wait for one. `None` means the budget is not limited.
+    pub fn acquire(&self, client: &str, budget: Budget, now: Instant) -> Option<Quota> {
+        self.take(client, budget, now, 1.0)
+    }
+
+    /// Like `acquire`, but only tells whether a token is left, without taking it.
+    pub fn peek(&self, client: &str, budget: Budget, now: Instant) -> Option<Quota> {
+        self.take(client, budget, now, 0.0)
+    }
+
+    fn take(&self, client: &str, budget: Budget, now: Instant, cost: f64) -> Option<Quota> {
+        let limit = self.limits.per_minute(budget);
+        if limit == 0 {
+            return None;
+        }
+        let capacity = f64::from(limit);
+        let per_second = capacity / 60.0;
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets
+            .entry((client.to_string(), budget))
+            .or_insert(Bucket {
+                tokens: capacity,
+                refilled_at: now,
+            });
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * per_second).min(capacity);
+        bucket.refilled_at = now;
+        let retry_after = if bucket.tokens >= 1.0 {
+            bucket.tokens -= cost;
+            None
+        } else {
+            Some(((1.0 - bucket.tokens) / per_second).ceil() as u64)
+        };
+        Some(Quota {
+            limit,
+            remaining: bucket.tokens.floor() as u32,
+            reset: ((capacity - bucket.tokens) / per_second).ceil() as u64,
+            retry_after,
+        })
+    }
+
+    /// Drops the buckets that have refilled completely at `now`, since a
+    /// fresh bucket behaves the same, and returns how many are left.
+    pub fn prune(&self, now: Instant) -> usize {
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.retain(|(_, budget), bucket| {
+            let capacity = f64::from(self.limits.per_minute(*budget));
+            let elapsed = now.saturating_duration_since(bucket.refilled_at);
+            bucket.tokens + elapsed.as_secs_f64() * capacity / 60.0 < capacity
+        });
+        buckets.len()
+    }
+
+    /// Authenticated callers are limited by who they are, anonymous ones by
+    /// the address they connect from.
+    fn client(&self, request: &Request) -> String {
+        match request
+            .extensions()
+            .get::<Caller>()
+            .and_then(|caller| caller.subject.as_deref())
+        {
+            Some(subject) => subject.to_string(),
+            None => self.address(request),
+        }
+    }
+
+    fn address(&self, request: &Request) -> String {
+        let forwarded = self
+            .limits
+            .trust_forwarded_for
+            .then(|| request.headers().get("x-forwarded-for"))
+            .flatten()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .map(|addr| addr.trim().to_string());
+        let peer = || {
+            request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_string())
+        };
+        format!(
+            "ip:{}",
+            forwarded
+                .or_else(peer)
+                .unwrap_or_else(|| "unknown".to_string())
+        )
+    }
+}
+
+/// Route layer that refuses requests over the client's budget with 429 and
+/// reports the budget in `RateLimit-*` headers. It needs the `Caller`, so it
+/// runs inside `authenticate`, behind `limit_failed_auth`.
+pub async fn limit(
+    extract::State(limiter): extract::State<RateLimiter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client = limiter.client(&request);
+    let Some(quota) = limiter.acquire(&client, Budget::of(request.method()), Instant::now()) else {
+        return next.run(request).await;
+    };
+    let res = match quota.retry_after {
+        Some(retry_after) => AppError::TooManyRequests { retry_after }.into_response(),
+        None => next.run(request).await,
+    };
+    with_quota(res, &quota)
+}
+
+/// Route layer that runs in front of `authenticate` and charges requests
+/// whose credentials are refused to a budget of the address they come from,
+/// kept apart from the one anonymous requests spend. Once that budget is
+/// spent, requests with credentials from the address are refused before
+/// they are looked up.
+pub async fn limit_failed_auth(
+    extract::State(limiter): extract::State<RateLimiter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let headers = request.headers();
+    if !headers.contains_key(auth::API_KEY_HEADER)
+        && !headers.contains_key(http::header::AUTHORIZATION)
+    {
+        return next.run(request).await;
+    }
+    let address = format!("auth-fail:{}", limiter.address(&request));
+    let budget = Budget::of(request.method());
+    if let Some(quota) = limiter.peek(&address, budget, Instant::now()) {
+        if let Some(retry_after) = quota.retry_after {
+            let res = AppError::TooManyRequests { retry_after }.into_response();
+            return with_quota(res, &quota);
+        }
+    }
+    let res = next.run(request).await;
+    if res.status() != http::StatusCode::UNAUTHORIZED {
+        return res;
+    }
+    match limiter.acquire(&address, budget, Instant::now()) {
+        Some(quota) => with_quota(res, &quota),
+        None => res,
+    }
+}
+
+/// Runs `prune` in the background once every minute, off the request path.
+pub fn spawn_prune(limiter: RateLimiter) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            limiter.prune(Instant::now());
+        }
+    });
+}
+
+fn with_quota(mut res: Response, quota: &Quota) -> Response {
+    let headers = res.headers_mut();
+    headers.insert("ratelimit-limit", HeaderValue::from(quota.limit));
+    headers.insert("ratelimit-remaining", HeaderValue::from(quota.remaining));
+    headers.insert("ratelimit-reset", HeaderValue::from(quota.reset));
+    res
+}
+
+#[test]
+fn test_rate_limiter() {
+    let limiter = RateLimiter::new(RateLimits {
+        reads_per_minute: 2,
+        writes_per_minute: 0,
+        trust_forwarded_for: false,
+    });
+    let start = Instant::now();
+    let quota = limiter.acquire("alice", Budget::Read, start).unwrap();
+    assert_eq!(quota.remaining, 1);
+    assert_eq!(quota.reset, 30);
+    assert!(limiter.acquire("alice", Budget::Read, start).is_some());
+    // the budget is spent until a token has refilled
+    let quota = limiter.acquire("alice", Budget::Read, start).unwrap();
+    assert_eq!(quota.remaining, 0);
+    assert_eq!(quota.retry_after, Some(30));
+    let later = start + Duration::from_secs(30);
+    let quota = limiter.acquire("alice", Budget::Read, later).unwrap();
+    assert_eq!(quota.retry_after, None);
+    // clients and budgets are counted apart
+    let quota = limiter.acquire("bob", Budget::Read, start).unwrap();
+    assert_eq!(quota.retry_after, None);
+    assert_eq!(limiter.acquire("alice", Budget::Write, start), None);
+    // only the buckets that have not refilled yet are kept, here alice's
+    assert_eq!(limiter.prune(later), 1);
+    assert_eq!(limiter.prune(later + Duration::from_secs(60)), 0);
+}
+
+#[sqlx::test]
+async fn test_limit_failed_auth(pool: sqlx::PgPool) -> sqlx::Result<()> {
+    use axum::middleware::from_fn_with_state;
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    #[derive(Clone, extract::FromRef)]
+    struct State {
+        pool: sqlx::PgPool,
+        config: auth::AuthConfig,
+        limiter: RateLimiter,
+    }
+
+    let state = State {
+        pool: pool.clone(),
+        config: auth::AuthConfig::default(),
+        limiter: RateLimiter::new(RateLimits {
+            reads_per_minute: 2,
+            ..Default::default()
+        }),
+    };
+    let app = axum::Router::new()
+        .route("/", get(|| async {}))
+        .route_layer(from_fn_with_state(state.clone(), limit))
+        .route_layer(from_fn_with_state(state.clone(), auth::authenticate))
+        .route_layer(from_fn_with_state(state.clone(), limit_failed_auth))
+        .with_state(state);
+    let send = |key: Option<&str>| {
+        let mut request = Request::get("/");
+        if let Some(key) = key {
+            request = request.header(auth::API_KEY_HEADER, key);
+        }
+        app.clone()
+            .oneshot(request.body(axum::body::Body::empty()).unwrap())
+    };
+    // a valid key is limited on its own budget, apart from its address
+    let (_, key) = auth::issue_key(&pool, "widget", "quotes:read").await?;
+    let res = send(Some(&key)).await.unwrap();
+    assert_eq!(res.status(), http::StatusCode::OK);
+    assert_eq!(res.headers()["ratelimit-remaining"], "1");
+    // anonymous requests spending the budget of the address do not hold up
+    // the ones that authenticate from there
+    let mut statuses = Vec::new();
+    for _ in 0..3 {
+        statuses.push(send(None).await.unwrap().status());
+    }
+    assert_eq!(
+        statuses,
+        [
+            http::StatusCode::OK,
+            http::StatusCode::OK,
+            http::StatusCode::TOO_MANY_REQUESTS,
+        ]
+    );
+    let res = send(Some(&key)).await.unwrap();
+    assert_eq!(res.status(), http::StatusCode::OK);
+    // refused keys spend a budget of their own for the address, and once it
+    // is spent requests from there are refused before their keys are looked up
+    let mut statuses = Vec::new();
+    for _ in 0..3 {
+        statuses.push(send(Some("qk_guess")).await.unwrap().status());
+    }
+    assert_eq!(
+        statuses,
+        [
+            http::StatusCode::UNAUTHORIZED,
+            http::StatusCode::UNAUTHORIZED,
+            http::StatusCode::TOO_MANY_REQUESTS,
+        ]
+    );
+    let res = send(Some(&key)).await.unwrap();
+    assert_eq!(res.status(), http::StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(res.headers()["ratelimit-remaining"], "0");
+    Ok(())
+}
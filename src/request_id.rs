@@ -0,0 +1,76 @@
+use axum::extract::Request;
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Longer ids from clients are replaced rather than logged.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// The id of the request being served, if any.
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Layer that keeps the `X-Request-Id` a client sent, or assigns a new one,
+/// so that the request span, the response and its error body all carry it.
+/// It has to wrap the `TraceLayer` for the span to see the id.
+pub async fn propagate(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let value = HeaderValue::from_str(&id).expect("request ids are valid header values");
+    request
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, value.clone());
+    let mut res = REQUEST_ID.scope(id, next.run(request)).await;
+    res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    res
+}
+
+#[tokio::test]
+async fn test_propagate() {
+    use crate::error::AppError;
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    let app = axum::Router::new()
+        .route("/", get(|| async { AppError::NotFound }))
+        .layer(axum::middleware::from_fn(propagate));
+    let send = |id: Option<&str>| {
+        let mut request = Request::get("/");
+        if let Some(id) = id {
+            request = request.header(REQUEST_ID_HEADER, id);
+        }
+        app.clone()
+            .oneshot(request.body(axum::body::Body::empty()).unwrap())
+    };
+    let problem = |res: Response| async {
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+    };
+    let res = send(Some("req-42")).await.unwrap();
+    assert_eq!(res.headers()[REQUEST_ID_HEADER], "req-42");
+    assert_eq!(problem(res).await["request_id"], "req-42");
+    // a missing or oversized id is replaced by a fresh one
+    let long = "x".repeat(MAX_REQUEST_ID_LENGTH + 1);
+    for id in [None, Some(long.as_str())] {
+        let res = send(id).await.unwrap();
+        let id = res.headers()[REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(uuid::Uuid::parse_str(&id).is_ok());
+        assert_eq!(problem(res).await["request_id"], id.as_str());
+    }
+}
@@ -0,0 +1,220 @@
+use crate::auth::Caller;
+use crate::error::AppError;
+use crate::etag;
+use crate::handlers::{self, CreateQuote, Tagged};
+use crate::metrics;
+use crate::pagination::{self, Page};
+use crate::telemetry::Observe;
+use crate::validation::{ValidPath, ValidQuery};
+use axum::{extract, http};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgExecutor, PgPool};
+
+/// A version of a quote as it was before a change replaced it. Revisions
+/// are numbered from 1 per quote and written by the `quotes` table itself.
+#[derive(Serialize, FromRow)]
+pub struct Revision {
+    revision: i32,
+    /// The change that replaced this version: `update`, `delete` or `restore`.
+    operation: String,
+    book: String,
+    author_id: Option<uuid::Uuid>,
+    quote: String,
+    /// The caller that made the change, when it is known.
+    actor: Option<String>,
+    recorded_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ListRevisions {
+    limit: Option<i64>,
+    cursor: Option<String>,
+}
+
+/// Fetches a revision of a live quote. The revisions of a quote in the
+/// trash are as hidden as the quote itself.
+async fn fetch_revision(
+    executor: impl PgExecutor<'_>,
+    id: uuid::Uuid,
+    revision: i32,
+) -> sqlx::Result<Option<Revision>> {
+    sqlx::query_as::<_, Revision>(
+        r#"
+        SELECT quote_revisions.* FROM quote_revisions
+        JOIN quotes ON quotes.id = quote_revisions.quote_id
+        WHERE quote_id = $1 AND revision = $2 AND deleted_at IS NULL
+        "#,
+    )
+    .bind(id)
+    .bind(revision)
+    .fetch_optional(executor)
+    .observe("fetch_revision")
+    .await
+}
+
+/// Lists the revisions of a live quote, newest first.
+pub async fn read_revisions(
+    extract::State(pool): extract::State<PgPool>,
+    ValidPath(id): ValidPath<uuid::Uuid>,
+    ValidQuery(params): ValidQuery<ListRevisions>,
+) -> Result<axum::Json<Page<Revision>>, AppError> {
+    let limit = pagination::page_size(params.limit);
+    let cursor = match params.cursor.as_deref() {
+        Some(raw) => Some(raw.parse::<i32>().map_err(|_| AppError::InvalidQuery {
+            param: Some("cursor"),
+            message: "cursor is malformed".to_string(),
+        })?),
+        None => None,
+    };
+    let mut conn = metrics::acquire(&pool).await?;
+    let exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM quotes WHERE id = $1 AND deleted_at IS NULL)",
+    )
+    .bind(id)
+    .fetch_one(&mut *conn)
+    .observe_one("quote_exists")
+    .await?;
+    if !exists {
+        return Err(AppError::NotFound);
+    }
+    let revisions = sqlx::query_as::<_, Revision>(
+        r#"
+        SELECT * FROM quote_revisions
+        WHERE quote_id = $1 AND ($2::integer IS NULL OR revision < $2)
+        ORDER BY revision DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(id)
+    .bind(cursor)
+    .bind(limit + 1)
+    .fetch_all(&mut *conn)
+    .observe("list_revisions")
+    .await?;
+    Ok(axum::Json(Page::from_rows(revisions, limit, |revision| {
+        revision.revision.to_string()
+    })))
+}
+
+pub async fn read_revision(
+    extract::State(pool): extract::State<PgPool>,
+    ValidPath((id, revision)): ValidPath<(uuid::Uuid, i32)>,
+) -> Result<axum::Json<Revision>, AppError> {
+    let mut conn = metrics::acquire(&pool).await?;
+    fetch_revision(&mut *conn, id, revision)
+        .await?
+        .map(axum::Json)
+        .ok_or(AppError::NotFound)
+}
+
+/// Puts the book, author and text of a revision back on a live quote. The
+/// revert is an update like any other, so it adds a revision of its own.
+pub async fn revert_quote(
+    extract::State(pool): extract::State<PgPool>,
+    ValidPath((id, revision)): ValidPath<(uuid::Uuid, i32)>,
+    caller: Caller,
+    headers: http::HeaderMap,
+) -> Result<Tagged, AppError> {
+    let mut conn = metrics::acquire(&pool).await?;
+    let revision = fetch_revision(&mut *conn, id, revision)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let payload = CreateQuote {
+        book: revision.book,
+        quote: revision.quote,
+        author_id: revision.author_id,
+    };
+    let if_match = etag::if_match(&headers);
+    let res = handlers::replace_quote(&mut *conn, id, &payload, &caller, if_match).await;
+    match res {
+        Ok(Some(quote)) => Ok(Tagged(quote)),
+        Ok(None) => Err(handlers::missing_or_modified(&mut *conn, id, &caller, false).await),
+        Err(err) => {
+            Err(
+                handlers::constraint_violation(&mut *conn, &payload.book, &payload.quote, err)
+                    .await,
+            )
+        }
+    }
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_revisions(pool: PgPool) -> sqlx::Result<()> {
+    let admin = crate::auth::caller("admin", crate::auth::Scope::Admin);
+    let id = uuid::Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap();
+    for quote in [
+        "In a hole there lived a hobbit.",
+        "A hobbit lived in a hole.",
+    ] {
+        sqlx::query("UPDATE quotes SET quote = $1 WHERE id = $2")
+            .bind(quote)
+            .bind(id)
+            .execute(&pool)
+            .await?;
+    }
+    // touching a quote without changing it keeps no revision
+    sqlx::query("UPDATE quotes SET updated_at = now() WHERE id = $1")
+        .bind(id)
+        .execute(&pool)
+        .await?;
+    let res = read_revisions(
+        extract::State(pool.clone()),
+        ValidPath(id),
+        ValidQuery(ListRevisions {
+            limit: Some(1),
+            cursor: None,
+        }),
+    )
+    .await;
+    let page = res.unwrap().0;
+    assert_eq!(page.data[0].revision, 2);
+    assert_eq!(page.data[0].quote, "In a hole there lived a hobbit.");
+    assert_eq!(page.next_cursor.as_deref(), Some("2"));
+
+    let res = revert_quote(
+        extract::State(pool.clone()),
+        ValidPath((id, 1)),
+        admin.clone(),
+        http::HeaderMap::new(),
+    )
+    .await;
+    let quote = serde_json::to_value(res.unwrap().0).unwrap();
+    assert_eq!(
+        quote["quote"],
+        "In a hole in the ground there lived a hobbit."
+    );
+    let res = read_revision(extract::State(pool.clone()), ValidPath((id, 3))).await;
+    let revision = res.unwrap().0;
+    assert_eq!(revision.quote, "A hobbit lived in a hole.");
+    assert_eq!(revision.operation, "update");
+    // changes made through the API name their caller, the others nobody
+    assert_eq!(revision.actor.as_deref(), Some("admin"));
+    let res = read_revision(extract::State(pool.clone()), ValidPath((id, 2))).await;
+    assert_eq!(res.unwrap().0.actor, None);
+    let res = read_revision(extract::State(pool.clone()), ValidPath((id, 4))).await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::NOT_FOUND)
+    );
+    // the history of a quote in the trash is hidden along with it
+    sqlx::query("UPDATE quotes SET deleted_at = now() WHERE id = $1")
+        .bind(id)
+        .execute(&pool)
+        .await?;
+    let res = read_revisions(
+        extract::State(pool.clone()),
+        ValidPath(id),
+        ValidQuery(ListRevisions::default()),
+    )
+    .await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::NOT_FOUND)
+    );
+    let res = read_revision(extract::State(pool), ValidPath((id, 1))).await;
+    assert_eq!(
+        res.err().map(|err| err.status()),
+        Some(http::StatusCode::NOT_FOUND)
+    );
+    Ok(())
+}
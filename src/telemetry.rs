@@ -0,0 +1,199 @@
+use crate::metrics;
+use axum::http::HeaderMap;
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use sqlx::postgres::PgQueryResult;
+use std::future::Future;
+use std::time::Instant;
+use tracing::Instrument;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+const DEFAULT_SERVICE_NAME: &str = "quotes";
+
+/// Reads the W3C trace context of the caller from request headers.
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// The trace a request continues, as named by its `traceparent` and
+/// `tracestate` headers; an empty context when it starts a new one.
+pub fn parent_context(headers: &HeaderMap) -> opentelemetry::Context {
+    TraceContextPropagator::new().extract(&HeaderExtractor(headers))
+}
+
+/// Rows a query returned or changed.
+pub trait Rows {
+    fn rows(&self) -> u64;
+}
+
+impl<T> Rows for Vec<T> {
+    fn rows(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl<T> Rows for Option<T> {
+    fn rows(&self) -> u64 {
+        self.is_some().into()
+    }
+}
+
+impl Rows for PgQueryResult {
+    fn rows(&self) -> u64 {
+        self.rows_affected()
+    }
+}
+
+/// Runs a query in a span named after its statement, which records the
+/// rows it yielded, and times it with `metrics::record_query`.
+pub trait Observe<T>: Future<Output = sqlx::Result<T>> + Sized {
+    fn observe(self, statement: &'static str) -> impl Future<Output = Self::Output> + Send
+    where
+        Self: Send,
+        T: Rows,
+    {
+        observed(self, statement, T::rows)
+    }
+
+    /// Like `observe`, for queries that yield exactly one row.
+    fn observe_one(self, statement: &'static str) -> impl Future<Output = Self::Output> + Send
+    where
+        Self: Send,
+    {
+        observed(self, statement, |_| 1)
+    }
+}
+
+impl<T, F: Future<Output = sqlx::Result<T>>> Observe<T> for F {}
+
+async fn observed<T>(
+    query: impl Future<Output = sqlx::Result<T>>,
+    statement: &'static str,
+    rows: fn(&T) -> u64,
+) -> sqlx::Result<T> {
+    let span = tracing::info_span!(
+        "query",
+        otel.name = statement,
+        db.system = "postgresql",
+        db.statement.name = statement,
+        db.rows = tracing::field::Empty,
+    );
+    let start = Instant::now();
+    let output = query.instrument(span.clone()).await;
+    if let Ok(output) = &output {
+        // OpenTelemetry has no unsigned integers, so u64 would be exported as text.
+        span.record("db.rows", rows(output) as i64);
+    }
+    metrics::record_query(statement, start.elapsed());
+    output
+}
+
+/// Logs to stdout at the levels in `RUST_LOG`, by default `info`, as one
+/// JSON object per line when `LOG_FORMAT=json`. When
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` is set, spans are also exported over OTLP
+/// as `OTEL_SERVICE_NAME`. The returned provider has to be shut down to
+/// flush the spans still queued for export.
+pub fn init() -> Option<TracerProvider> {
+    let provider = std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT").map(|_| {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .build()
+            .expect("OTEL_EXPORTER_OTLP_ENDPOINT must be a valid endpoint");
+        let service_name =
+            std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| DEFAULT_SERVICE_NAME.to_string());
+        TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_resource(Resource::new([KeyValue::new("service.name", service_name)]))
+            .build()
+    });
+    // Exported traces keep their query spans whatever the log level.
+    let otel = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer(DEFAULT_SERVICE_NAME))
+            .with_filter(LevelFilter::INFO)
+    });
+    let fmt = tracing_subscriber::fmt::layer().with_target(false);
+    let fmt = match std::env::var("LOG_FORMAT").as_deref() {
+        Ok("json") => fmt
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+        _ => fmt.compact().boxed(),
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::registry()
+        .with(fmt.with_filter(filter))
+        .with(otel)
+        .init();
+    provider
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_query_spans(pool: sqlx::PgPool) -> sqlx::Result<()> {
+    use opentelemetry::trace::{SpanId, TraceId};
+    use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+    use tracing::Instrument;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    let exporter = InMemorySpanExporter::default();
+    let provider = TracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let _guard = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")))
+        .set_default();
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "traceparent",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+            .parse()
+            .unwrap(),
+    );
+    let span = tracing::info_span!("request");
+    span.set_parent(parent_context(&headers));
+    let res = crate::handlers::read_quotes(
+        axum::extract::State(pool),
+        crate::handlers::ListQuotes::default(),
+    )
+    .instrument(span)
+    .await;
+    assert!(res.is_ok());
+
+    let spans = exporter.get_finished_spans().unwrap();
+    let request = spans.iter().find(|span| span.name == "request").unwrap();
+    let query = spans
+        .iter()
+        .find(|span| span.name == "list_quotes")
+        .unwrap();
+    // the request continues the trace of its caller and the query is part of it
+    let trace_id = TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap();
+    assert_eq!(request.span_context.trace_id(), trace_id);
+    assert_eq!(
+        request.parent_span_id,
+        SpanId::from_hex("00f067aa0ba902b7").unwrap()
+    );
+    assert_eq!(query.span_context.trace_id(), trace_id);
+    assert_eq!(query.parent_span_id, request.span_context.span_id());
+    assert!(query
+        .attributes
+        .contains(&KeyValue::new("db.statement.name", "list_quotes")));
+    assert!(query.attributes.contains(&KeyValue::new("db.rows", 1)));
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use crate::config::env_number;
+use crate::telemetry::Observe;
+use sqlx::PgPool;
+use std::time::Duration;
+
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How long a deleted quote stays restorable, read from `TRASH_RETENTION_DAYS`.
+pub fn retention_from_env() -> chrono::Duration {
+    chrono::Duration::days(env_number("TRASH_RETENTION_DAYS", DEFAULT_RETENTION_DAYS))
+}
+
+/// Permanently removes the quotes that were deleted more than `retention` ago.
+pub async fn purge(pool: &PgPool, retention: chrono::Duration) -> sqlx::Result<u64> {
+    let res = sqlx::query("DELETE FROM quotes WHERE deleted_at < $1")
+        .bind(chrono::Utc::now() - retention)
+        .execute(pool)
+        .observe("purge_trash")
+        .await?;
+    Ok(res.rows_affected())
+}
+
+/// Runs `purge` in the background once every hour.
+pub fn spawn_purge(pool: PgPool, retention: chrono::Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match purge(&pool, retention).await {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!(purged, "purged quotes from the trash"),
+                Err(err) => tracing::error!(error = %err, "failed to purge the trash"),
+            }
+        }
+    });
+}
+
+#[sqlx::test(fixtures("quotes"))]
+async fn test_purge(pool: PgPool) -> sqlx::Result<()> {
+    sqlx::query("UPDATE quotes SET deleted_at = now() - interval '2 days'")
+        .execute(&pool)
+        .await?;
+    // quotes deleted within the retention period are kept
+    assert_eq!(purge(&pool, chrono::Duration::days(3)).await?, 0);
+    assert_eq!(purge(&pool, chrono::Duration::days(1)).await?, 1);
+    Ok(())
+}
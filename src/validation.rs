@@ -0,0 +1,310 @@
+use crate::config::env_number;
+use crate::error::AppError;
+use axum::extract::{self, FromRef, FromRequest, FromRequestParts, Request};
+use axum::http::request::Parts;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use unicode_normalization::UnicodeNormalization;
+
+const DEFAULT_MAX_BOOK_LENGTH: usize = 256;
+const DEFAULT_MAX_AUTHOR_LENGTH: usize = 256;
+const DEFAULT_MAX_QUOTE_LENGTH: usize = 4096;
+
+/// Upper bounds on the text fields of a payload, counted in characters.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub max_book_length: usize,
+    pub max_author_length: usize,
+    pub max_quote_length: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_book_length: DEFAULT_MAX_BOOK_LENGTH,
+            max_author_length: DEFAULT_MAX_AUTHOR_LENGTH,
+            max_quote_length: DEFAULT_MAX_QUOTE_LENGTH,
+        }
+    }
+}
+
+impl Limits {
+    /// Reads `MAX_BOOK_LENGTH`, `MAX_AUTHOR_LENGTH` and `MAX_QUOTE_LENGTH`,
+    /// falling back to the defaults.
+    pub fn from_env() -> Self {
+        Self {
+            max_book_length: env_number("MAX_BOOK_LENGTH", DEFAULT_MAX_BOOK_LENGTH),
+            max_author_length: env_number("MAX_AUTHOR_LENGTH", DEFAULT_MAX_AUTHOR_LENGTH),
+            max_quote_length: env_number("MAX_QUOTE_LENGTH", DEFAULT_MAX_QUOTE_LENGTH),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+/// A payload that normalizes its fields and checks them against `Limits`.
+pub trait Validate {
+    fn validate(&mut self, limits: &Limits) -> Result<(), Vec<FieldError>>;
+}
+
+/// Collects the errors of every field of a payload before reporting them.
+#[derive(Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    /// Trims and NFC-normalizes `value` in place, then requires it to be
+    /// non-empty and at most `max_length` characters long.
+    pub fn text(
+        &mut self,
+        field: &'static str,
+        value: &mut String,
+        max_length: usize,
+    ) -> &mut Self {
+        *value = value.trim().nfc().collect();
+        if value.is_empty() {
+            self.errors.push(FieldError {
+                field,
+                message: "must not be empty".to_string(),
+            });
+        } else if value.chars().count() > max_length {
+            self.errors.push(FieldError {
+                field,
+                message: format!("must be at most {} characters", max_length),
+            });
+        }
+        self
+    }
+
+    /// Like `text`, for a field that may be left out of the payload.
+    pub fn optional_text(
+        &mut self,
+        field: &'static str,
+        value: &mut Option<String>,
+        max_length: usize,
+    ) -> &mut Self {
+        match value {
+            Some(value) => self.text(field, value, max_length),
+            None => self,
+        }
+    }
+
+    /// Normalizes an ISBN-10 or ISBN-13 to its bare digits and checks its check digit.
+    pub fn optional_isbn(&mut self, field: &'static str, value: &mut Option<String>) -> &mut Self {
+        let Some(isbn) = value else {
+            return self;
+        };
+        *isbn = isbn
+            .chars()
+            .filter(|c| !matches!(c, '-' | ' '))
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        let digits: Option<Vec<u32>> = isbn
+            .chars()
+            .enumerate()
+            .map(|(i, c)| match c {
+                'X' if isbn.len() == 10 && i == 9 => Some(10),
+                _ => c.to_digit(10),
+            })
+            .collect();
+        let valid = match digits {
+            Some(digits) if digits.len() == 10 => {
+                digits
+                    .iter()
+                    .zip((1..=10).rev())
+                    .map(|(digit, weight)| digit * weight)
+                    .sum::<u32>()
+                    % 11
+                    == 0
+            }
+            Some(digits) if digits.len() == 13 => {
+                digits
+                    .iter()
+                    .zip([1, 3].into_iter().cycle())
+                    .map(|(digit, weight)| digit * weight)
+                    .sum::<u32>()
+                    % 10
+                    == 0
+            }
+            _ => false,
+        };
+        if !valid {
+            self.errors.push(FieldError {
+                field,
+                message: "must be a valid ISBN-10 or ISBN-13".to_string(),
+            });
+        }
+        self
+    }
+
+    /// Normalizes every tag with `normalize_tag` and drops the duplicates,
+    /// then requires at most `max_count` tags of at most `max_length`
+    /// characters, none of them empty.
+    pub fn tags(
+        &mut self,
+        field: &'static str,
+        values: &mut Vec<String>,
+        max_count: usize,
+        max_length: usize,
+    ) -> &mut Self {
+        let mut seen = std::collections::HashSet::new();
+        *values = values
+            .iter()
+            .map(|value| normalize_tag(value))
+            .filter(|value| seen.insert(value.clone()))
+            .collect();
+        if values.len() > max_count {
+            self.errors.push(FieldError {
+                field,
+                message: format!("must contain at most {} tags", max_count),
+            });
+        }
+        if values.iter().any(|value| value.is_empty()) {
+            self.errors.push(FieldError {
+                field,
+                message: "must not contain empty tags".to_string(),
+            });
+        }
+        if values
+            .iter()
+            .any(|value| value.chars().count() > max_length)
+        {
+            self.errors.push(FieldError {
+                field,
+                message: format!("tags must be at most {} characters", max_length),
+            });
+        }
+        self
+    }
+
+    pub fn optional_range(
+        &mut self,
+        field: &'static str,
+        value: Option<i32>,
+        range: std::ops::RangeInclusive<i32>,
+    ) -> &mut Self {
+        if value.is_some_and(|value| !range.contains(&value)) {
+            self.errors.push(FieldError {
+                field,
+                message: format!("must be between {} and {}", range.start(), range.end()),
+            });
+        }
+        self
+    }
+
+    pub fn finish(&mut self) -> Result<(), Vec<FieldError>> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+}
+
+/// The stored form of a tag: trimmed, NFC-normalized and lowercased, so that
+/// tags compare without regard to case.
+pub fn normalize_tag(value: &str) -> String {
+    value.trim().nfc().collect::<String>().to_lowercase()
+}
+
+/// JSON body extractor that runs `Validate` on the payload and reports both
+/// malformed JSON and invalid fields as problem details.
+pub struct ValidJson<T>(pub T);
+
+#[axum::async_trait]
+impl<S, T> FromRequest<S> for ValidJson<T>
+where
+    T: DeserializeOwned + Validate,
+    Limits: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Json(mut payload) = axum::Json::<T>::from_request(req, state)
+            .await
+            .map_err(AppError::InvalidBody)?;
+        payload
+            .validate(&Limits::from_ref(state))
+            .map_err(AppError::Validation)?;
+        Ok(Self(payload))
+    }
+}
+
+/// Path extractor that reports a malformed segment, such as an id that is
+/// not a UUID, as problem details instead of plain text.
+pub struct ValidPath<T>(pub T);
+
+#[axum::async_trait]
+impl<S, T> FromRequestParts<S> for ValidPath<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let extract::Path(value) = extract::Path::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| AppError::InvalidQuery {
+                param: None,
+                message: rejection.body_text(),
+            })?;
+        Ok(Self(value))
+    }
+}
+
+/// Query string extractor that reports unknown or malformed parameters as
+/// problem details instead of plain text.
+pub struct ValidQuery<T>(pub T);
+
+#[axum::async_trait]
+impl<S, T> FromRequestParts<S> for ValidQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let extract::Query(value) = extract::Query::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| AppError::InvalidQuery {
+                param: None,
+                message: rejection.body_text(),
+            })?;
+        Ok(Self(value))
+    }
+}
+
+#[tokio::test]
+async fn test_rejections_are_problems() {
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    #[derive(serde::Deserialize)]
+    struct Params {
+        date: chrono::NaiveDate,
+    }
+
+    let app = axum::Router::new()
+        .route("/:id", get(|_: ValidPath<uuid::Uuid>| async {}))
+        .route(
+            "/",
+            get(|ValidQuery(params): ValidQuery<Params>| async move { params.date.to_string() }),
+        );
+    for uri in ["/not-a-uuid", "/?date=garbage"] {
+        let request = Request::get(uri).body(axum::body::Body::empty()).unwrap();
+        let res = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(res.status(), axum::http::StatusCode::BAD_REQUEST);
+        assert_eq!(
+            res.headers()[axum::http::header::CONTENT_TYPE],
+            "application/problem+json"
+        );
+    }
+}